@@ -1,10 +1,35 @@
 //! 导出方法
 
-use crate::module::grid::{Grid, GridPoint, GridProps, GridResultPoint, Obstacle, ObstacleType, ThreeGrid, ThreeGridResultPoint};
+use crate::error::Error;
+use crate::module::behaviour::{Behaviour, BehaviourNode};
+use crate::module::coord::CoordinateSystem;
+use crate::module::dynamic::{DynamicObstacle, DynamicSchedule};
+use crate::module::fog::BeliefCell;
+use crate::module::grid::{Grid, GridPoint, GridProps, Obstacle, ThreeGrid, ThreeGridResultPoint};
+use crate::module::kinematics::{Kinematics, MotionModel};
+use crate::module::mapf::MapfSolver;
+use crate::module::marker::{Marker, MarkerKind};
+use crate::module::obstacle::{ObstacleDef, ObstacleRegistry};
+use crate::module::raycast::{clamp_ray_distance, line_of_sight, raycast, RayHit, RayMask};
 use crate::module::robot::{Robot, RobotState, Vec3};
-use crate::{PILLAR_SIZE, ROCK_SIZE_DEPTH, ROCK_SIZE_WITH};
+use crate::module::robots::{RobotData, RobotGoal, RobotRegistry, TickResult, TimedPath};
+use crate::module::script::{ScriptHost, ScriptOutput};
+use crate::module::sensor::{SensorConfig, SensorReading};
+use crate::module::session::{Divergence, Recorder, Replay, ReplayStatus, SessionInput, SessionLog};
+use crate::module::shape::ObstacleShape;
+use crate::module::simulation::{Simulation, SimulationSnapshot, SimulationStatus, TICK_DELTA};
+use crate::module::tracking::Tracking;
+use crate::module::world::WorldSnapshot;
+use crate::module::zone::{Zone, ZoneEffect, ZoneShape};
+use crate::SPEED;
+use log::error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, PathBuf};
 use std::sync::Mutex;
-use tauri::State;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 // 获取初始化属性
 #[tauri::command]
@@ -14,92 +39,842 @@ pub fn get_init_props() -> GridProps {
 
 // Three.js 坐标系 → Rust 格子坐标
 #[tauri::command]
-pub fn world_to_grid(grid: ThreeGrid) -> ThreeGridResultPoint {
-    Grid::world_to_grid(&grid)
+pub fn world_to_grid(grid: ThreeGrid, grid_state: State<Mutex<Grid>>) -> Result<ThreeGridResultPoint, String> {
+    let grid_state = grid_state.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid_state.world_to_grid(&grid))
 }
 
-// Rust 格子坐标 → Three.js 坐标系
+// Rust 格子坐标 → 格子锚点的世界坐标
 #[tauri::command]
-pub fn grid_to_world(grid: GridPoint) -> GridResultPoint {
-    Grid::grid_to_world(&grid)
+pub fn cell_to_world(cell: GridPoint, grid: State<Mutex<Grid>>) -> Result<ThreeGrid, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.cell_to_world(&cell))
+}
+
+// 获取坐标系
+#[tauri::command]
+pub fn get_coordinate_system(grid: State<Mutex<Grid>>) -> Result<CoordinateSystem, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(*grid.coords())
 }
 
 // 获取 robot 坐标
 #[tauri::command]
-pub fn get_robot_point(robot: State<Mutex<Robot>>) -> GridResultPoint {
-    let robot = robot.lock().unwrap();
-    robot.get_point()
+pub fn get_robot_point(id: u32, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>) -> Result<ThreeGrid, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(robots.get(id)?.get_point(&grid))
+}
+
+// 推送一次更新产生的事件
+fn emit_tick(app: &AppHandle, result: &TickResult) {
+    if !result.dynamic_events.is_empty() {
+        let _ = app.emit("dynamic-obstacle", &result.dynamic_events);
+    }
+
+    if !result.zone_events.is_empty() {
+        let _ = app.emit("zone-event", &result.zone_events);
+    }
+
+    if !result.beliefs.is_empty() {
+        let _ = app.emit("belief-update", &result.beliefs);
+    }
+
+    for id in &result.explored {
+        let _ = app.emit("exploration-complete", id);
+    }
+
+    if !result.readings.is_empty() {
+        let _ = app.emit("sensor-reading", &result.readings);
+    }
+
+    if !result.animations.is_empty() {
+        let _ = app.emit("animation", &result.animations);
+    }
+
+    if !result.behaviours.is_empty() {
+        let _ = app.emit("behaviour", &result.behaviours);
+    }
+}
+
+/**
+  推进机器人脚本, 推送脚本事件并记录脚本产生的输入, 在更新机器人之前执行, 调用时不能持有任何锁
+  ```
+   - 等待脚本发来请求时只持有 scripts 的锁
+   - 处理请求时按 robots → grid → scripts → recorder 的顺序加锁
+  ```
+*/
+fn tick_scripts(app: &AppHandle, delta: f32, only: Option<u32>) -> Result<(), String> {
+    let robots = app.state::<Mutex<RobotRegistry>>();
+    let grid = app.state::<Mutex<Grid>>();
+    let scripts = app.state::<Mutex<ScriptHost>>();
+    let recorder = app.state::<Mutex<Recorder>>();
+    scripts.lock().map_err(|_| "Mutex scripts poisoned")?.begin(only);
+
+    loop {
+        scripts.lock().map_err(|_| "Mutex scripts poisoned")?.wait();
+
+        let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+        let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+        let mut scripts = scripts.lock().map_err(|_| "Mutex scripts poisoned")?;
+        let mut recorder = recorder.lock().map_err(|_| "Mutex recorder poisoned")?;
+        let mut output = ScriptOutput::default();
+        let waiting = scripts.step(&mut grid, &mut robots, delta, &mut output);
+        if !output.events.is_empty() {
+            let _ = app.emit("script", &output.events);
+        }
+
+        for input in output.inputs {
+            recorder.record(input);
+        }
+
+        if !waiting {
+            return Ok(());
+        }
+    }
 }
 
+/**
+  文件命令只能读写应用数据目录下的文件
+  ```
+   - path 为相对应用数据目录的路径, 不能是绝对路径或包含 `..`
+   - 自动创建所在目录
+  ```
+*/
+fn data_path(app: &AppHandle, path: PathBuf) -> Result<PathBuf, String> {
+    if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(Error::convert_string(&format!("path `{}` must be relative to the app data directory", path.display())));
+    }
+
+    let path = app.path().app_data_dir().map_err(|err| Error::Error(err.to_string()).to_string())?.join(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::Error(err.to_string()).to_string())?;
+    }
+
+    Ok(path)
+}
+
+// 只更新一个机器人, 不推进世界时间(动态障碍物), 多个机器人请使用 `tick_robots`
 #[tauri::command]
-pub fn on_update_robot_position(delta: f32, robot: State<Mutex<Robot>>) -> RobotState {
-    let mut robot = robot.lock().unwrap();
-    robot.update(delta);
-    RobotState {
-        position: robot.get_current(),
-        is_moving: robot.get_moving(),
-        rotation_y: robot.get_rotation_y(),
-        path_index: robot.get_path_index(),
+pub fn on_update_robot_position(id: u32, delta: f32, app: AppHandle, simulation: State<Mutex<Simulation>>, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<RobotState, String> {
+    let running = simulation.lock().map_err(|_| "Mutex simulation poisoned")?.is_running();
+
+    // 模拟运行时由后台线程推进
+    if running {
+        let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+        return Ok(robots.get(id)?.state());
     }
+
+    tick_scripts(&app, delta, Some(id))?;
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let mut recorder = recorder.lock().map_err(|_| "Mutex recorder poisoned")?;
+    let result = robots.update(&mut grid, id, delta)?;
+    emit_tick(&app, &result);
+    recorder.record_frame(SessionInput::Update { id, delta }, robots.states());
+    Ok(robots.get(id)?.state())
 }
 
+// 推进世界时间并更新所有机器人
 #[tauri::command]
-pub fn set_robot_target(x: f32, z: f32, robot: State<Mutex<Robot>>, grid: State<Mutex<Grid>>) -> Result<Vec<Vec3>, String> {
-    let mut robot = robot.lock().map_err(|_| "Mutex robot poisoned")?;
-    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
-    let points = robot.set_target(&grid, x, z);
+pub fn tick_robots(delta: f32, app: AppHandle, simulation: State<Mutex<Simulation>>, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<RobotState>, String> {
+    let running = simulation.lock().map_err(|_| "Mutex simulation poisoned")?.is_running();
 
-    println!("Robot target updated to: ({}, {})", x, z);
+    // 模拟运行时由后台线程推进
+    if running {
+        let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+        return Ok(robots.states());
+    }
+
+    tick_scripts(&app, delta, None)?;
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let mut recorder = recorder.lock().map_err(|_| "Mutex recorder poisoned")?;
+    let result = robots.tick(&mut grid, delta);
+    emit_tick(&app, &result);
+    recorder.record_frame(SessionInput::Tick { delta }, result.states.clone());
+    Ok(result.states)
+}
+
+// 生成机器人
+#[tauri::command]
+pub fn spawn_robot(x: f32, z: f32, speed: Option<f32>, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<RobotState, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let speed = speed.unwrap_or(SPEED);
+    let state = robots.spawn(&mut grid, x, z, speed)?.state();
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Spawn { x, z, speed });
+    Ok(state)
+}
+
+// 移除机器人
+#[tauri::command]
+pub fn despawn_robot(id: u32, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    robots.despawn(&mut grid, id)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Despawn { id });
+    Ok(())
+}
+
+// 获取所有机器人状态
+#[tauri::command]
+pub fn get_robots(robots: State<Mutex<RobotRegistry>>) -> Result<Vec<RobotState>, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.states())
+}
+
+#[tauri::command]
+pub fn set_robot_target(id: u32, x: f32, z: f32, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Vec3>, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let points = robots.set_target(&mut grid, id, x, z)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Target { id, x, z });
+
+    println!("Robot {} target updated to: ({}, {})", id, x, z);
     Ok(points)
 }
 
 // 清除路径
 #[tauri::command]
-pub fn clear_robot_path(robot: State<Mutex<Robot>>) -> Result<(), String> {
-    let mut robot = robot.lock().map_err(|_| "Mutex robot poisoned")?;
-    robot.clear_path();
+pub fn clear_robot_path(id: u32, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.clear_path();
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Clear { id });
 
     Ok(())
 }
 
-// 设置动作
+// 立即推送命令产生的动作|表情切换, 表情播放结束的切换随 tick 推送
+fn emit_animation(app: &AppHandle, robot: &mut Robot) {
+    let transitions = robot.take_animation_transitions();
+    if !transitions.is_empty() {
+        let _ = app.emit("animation", vec![RobotData { robot_id: robot.get_id(), data: transitions }]);
+    }
+}
+
+// 设置动作, 不允许的切换返回错误
 #[tauri::command]
-pub fn set_robot_action(action: String, robot: State<Mutex<Robot>>) {
-    let mut robot = robot.lock().unwrap();
-    robot.set_action(action);
+pub fn set_robot_action(id: u32, action: String, app: AppHandle, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<RobotState, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let robot = robots.get_mut(id)?;
+    robot.set_action(&action)?;
+    emit_animation(&app, robot);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Action { id, action });
+    Ok(robot.state())
 }
 
-// 设置表情
+// 播放表情, 播放结束后回到当前动作
 #[tauri::command]
-pub fn set_robot_emote(emote: String, robot: State<Mutex<Robot>>) {
-    let mut robot = robot.lock().unwrap();
-    robot.set_emote(emote);
+pub fn set_robot_emote(id: u32, emote: String, app: AppHandle, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<RobotState, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let robot = robots.get_mut(id)?;
+    robot.set_emote(&emote)?;
+    emit_animation(&app, robot);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Emote { id, emote });
+    Ok(robot.state())
 }
 
 // 放置小红旗
 #[tauri::command]
-pub fn set_place_flag(x: f32, z: f32, grid: State<Mutex<Grid>>) -> Result<bool, String> {
+pub fn set_place_flag(x: f32, z: f32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<bool, String> {
     let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
-    Ok(grid.place_flag(x, z))
+    let placed = grid.place_flag(x, z);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Flag { x, z });
+    Ok(placed)
 }
 
-// 随机生成石头
+// 添加标记点
+#[tauri::command]
+pub fn add_marker(name: String, kind: MarkerKind, x: f32, z: f32, metadata: Option<serde_json::Value>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Marker, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let metadata = metadata.unwrap_or_default();
+    let marker = grid.add_marker(&name, kind, x, z, metadata.clone())?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::AddMarker { name, kind, x, z, metadata });
+    Ok(marker)
+}
+
+// 移动标记点
+#[tauri::command]
+pub fn move_marker(id: u32, x: f32, z: f32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Marker, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let marker = grid.move_marker(id, x, z)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::MoveMarker { id, x, z });
+    Ok(marker)
+}
+
+// 删除标记点
+#[tauri::command]
+pub fn remove_marker(id: u32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Marker, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let marker = grid.remove_marker(id)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::RemoveMarker { id });
+    Ok(marker)
+}
+
+// 获取所有标记点
+#[tauri::command]
+pub fn get_markers(grid: State<Mutex<Grid>>) -> Result<Vec<Marker>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.markers().to_vec())
+}
+
+// 按名字前往标记点
 #[tauri::command]
-pub fn generate_rocks(nums: usize, grid: State<Mutex<Grid>>) -> Result<Vec<Obstacle>, String> {
+pub fn set_robot_target_to_marker(id: u32, name: String, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Vec3>, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let marker = grid.get_marker_by_name(&name).cloned().ok_or_else(|| format!("marker `{}` not found", name))?;
+    let points = robots.set_target(&mut grid, id, marker.x, marker.z)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Target { id, x: marker.x, z: marker.z });
+    Ok(points)
+}
+
+// 随机生成障碍物, 不传 seed 时随机选一个, 录制时记录实际使用的 seed
+fn generate(kind: &str, nums: usize, seed: Option<u64>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Obstacle>, String> {
     let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
-    Ok(grid.generate_obstacle(nums, ROCK_SIZE_WITH, ROCK_SIZE_DEPTH, ObstacleType::Rock))
+    let seed = seed.unwrap_or_else(rand::random);
+    let obstacles = grid.generate_obstacle(nums, kind, seed)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Generate { kind: kind.to_string(), nums, seed });
+    Ok(obstacles)
+}
+
+// 随机生成石头
+#[tauri::command]
+pub fn generate_rocks(nums: usize, seed: Option<u64>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Obstacle>, String> {
+    generate("rock", nums, seed, grid, recorder)
 }
 
 // 随机生成柱子
 #[tauri::command]
-pub fn generate_pillars(nums: usize, grid: State<Mutex<Grid>>) -> Result<Vec<Obstacle>, String> {
+pub fn generate_pillars(nums: usize, seed: Option<u64>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Obstacle>, String> {
+    generate("pillar", nums, seed, grid, recorder)
+}
+
+// 随机生成指定类型的障碍物
+#[tauri::command]
+pub fn generate_obstacles(kind: String, nums: usize, seed: Option<u64>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<Obstacle>, String> {
+    generate(&kind, nums, seed, grid, recorder)
+}
+
+// 获取障碍物类型定义
+#[tauri::command]
+pub fn get_obstacle_types(grid: State<Mutex<Grid>>) -> Result<Vec<ObstacleDef>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.registry().defs().to_vec())
+}
+
+// 从定义文件加载障碍物类型, 会清除现有障碍物
+#[tauri::command]
+pub fn load_obstacle_types(path: PathBuf, app: AppHandle, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<ObstacleDef>, String> {
+    let path = data_path(&app, path)?;
+    let registry = ObstacleRegistry::from_file(&path)?;
     let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
-    Ok(grid.generate_obstacle(nums, PILLAR_SIZE, PILLAR_SIZE, ObstacleType::Pillar))
+    grid.set_registry(registry);
+    let types = grid.registry().defs().to_vec();
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::LoadTypes { types: types.clone() });
+    Ok(types)
 }
 
 // 随机生成柱子
 #[tauri::command]
-pub fn clear_obstacles(grid: State<Mutex<Grid>>) -> Result<(), String> {
+pub fn clear_obstacles(grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    grid.clear_obstacles();
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::ClearObstacles);
+    Ok(())
+}
+
+// 获取所有障碍物
+#[tauri::command]
+pub fn get_obstacles(grid: State<Mutex<Grid>>) -> Result<Vec<Obstacle>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.obstacles().to_vec())
+}
+
+// 在指定格子添加障碍物
+#[tauri::command]
+pub fn add_obstacle(kind: String, gx: i32, gz: i32, width: Option<usize>, depth: Option<usize>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Obstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let obstacle = grid.add_obstacle(&kind, gx, gz, width, depth)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::AddObstacle { kind, gx, gz, width, depth });
+    Ok(obstacle)
+}
+
+// 删除障碍物
+#[tauri::command]
+pub fn remove_obstacle(id: u32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Obstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let obstacle = grid.remove_obstacle(id)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::RemoveObstacle { id });
+    Ok(obstacle)
+}
+
+// 移动障碍物
+#[tauri::command]
+pub fn move_obstacle(id: u32, gx: i32, gz: i32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Obstacle, String> {
     let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
-    Ok(grid.clear_obstacles())
+    let obstacle = grid.move_obstacle(id, gx, gz)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::MoveObstacle { id, gx, gz });
+    Ok(obstacle)
+}
+
+// 修改障碍物尺寸
+#[tauri::command]
+pub fn resize_obstacle(id: u32, width: usize, depth: usize, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Obstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let obstacle = grid.resize_obstacle(id, width, depth)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::ResizeObstacle { id, width, depth });
+    Ok(obstacle)
+}
+
+// 查询覆盖某个坐标的障碍物
+#[tauri::command]
+pub fn get_obstacle_at(x: f32, z: f32, grid: State<Mutex<Grid>>) -> Result<Option<Obstacle>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.get_obstacle_at(x, z))
+}
+
+// 添加旋转矩形/圆形/多边形障碍物
+#[tauri::command]
+pub fn add_shaped_obstacle(kind: String, shape: ObstacleShape, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Obstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let obstacle = grid.add_shaped_obstacle(&kind, shape.clone())?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::AddShaped { kind, shape });
+    Ok(obstacle)
+}
+
+// 设置为动态障碍物(门、闸门、定时路障)
+#[tauri::command]
+pub fn set_obstacle_dynamic(id: u32, schedule: Option<DynamicSchedule>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<DynamicObstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let dynamic = grid.make_dynamic(id, schedule)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Dynamic { id, schedule });
+    Ok(dynamic)
+}
+
+// 打开|关闭动态障碍物
+#[tauri::command]
+pub fn set_obstacle_open(id: u32, open: bool, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<DynamicObstacle, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let dynamic = grid.set_dynamic_open(id, open)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Open { id, open });
+    Ok(dynamic)
+}
+
+// 获取所有动态障碍物状态
+#[tauri::command]
+pub fn get_dynamic_obstacles(grid: State<Mutex<Grid>>) -> Result<Vec<DynamicObstacle>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.dynamics().to_vec())
+}
+
+// 获取被机器人占用的格子
+#[tauri::command]
+pub fn get_occupied_cells(grid: State<Mutex<Grid>>) -> Result<Vec<GridPoint>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.occupied_cells())
+}
+
+// 添加区域
+#[tauri::command]
+pub fn add_zone(name: String, shape: ZoneShape, effect: ZoneEffect, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Zone, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let zone = grid.add_zone(&name, shape.clone(), effect)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::AddZone { name, shape, effect });
+    Ok(zone)
+}
+
+// 删除区域
+#[tauri::command]
+pub fn remove_zone(id: u32, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Zone, String> {
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let zone = grid.remove_zone(id)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::RemoveZone { id });
+    Ok(zone)
+}
+
+// 获取所有区域
+#[tauri::command]
+pub fn get_zones(grid: State<Mutex<Grid>>) -> Result<Vec<Zone>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(grid.zones().to_vec())
+}
+
+// 保存世界(障碍物、标记点、区域)
+#[tauri::command]
+pub fn save_world(path: PathBuf, app: AppHandle, grid: State<Mutex<Grid>>) -> Result<(), String> {
+    let path = data_path(&app, path)?;
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    grid.snapshot().save(&path)
+}
+
+// 加载世界
+#[tauri::command]
+pub fn load_world(path: PathBuf, app: AppHandle, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<WorldSnapshot, String> {
+    let path = data_path(&app, path)?;
+    let snapshot = WorldSnapshot::load(&path)?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    grid.restore(snapshot.clone())?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::LoadWorld { world: snapshot });
+    Ok(grid.snapshot())
+}
+
+// 射线检测, 返回第一个阻挡的格子和障碍物, max_distance 最多 100 个格子
+#[tauri::command]
+pub fn raycast_grid(origin: ThreeGrid, direction: ThreeGrid, max_distance: f32, mask: Option<RayMask>, grid: State<Mutex<Grid>>) -> Result<Option<RayHit>, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let max_distance = clamp_ray_distance(&grid, max_distance)?;
+    Ok(raycast(&grid, origin, direction, max_distance, mask.unwrap_or_default()))
+}
+
+// 两点之间是否可见
+#[tauri::command]
+pub fn has_line_of_sight(from: ThreeGrid, to: ThreeGrid, mask: Option<RayMask>, grid: State<Mutex<Grid>>) -> Result<bool, String> {
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    Ok(line_of_sight(&grid, from, to, mask.unwrap_or_default()))
+}
+
+// 机器人能否看到标记点
+#[tauri::command]
+pub fn can_robot_see_marker(id: u32, name: String, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>) -> Result<bool, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let marker = grid.get_marker_by_name(&name).ok_or_else(|| format!("marker `{}` not found", name))?;
+
+    let current = robots.get(id)?.get_current();
+    let from = ThreeGrid { x: current.x, z: current.z };
+    Ok(line_of_sight(&grid, from, ThreeGrid { x: marker.x, z: marker.z }, RayMask::Sight))
+}
+
+// 获取传感器配置
+#[tauri::command]
+pub fn get_robot_sensors(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Vec<SensorConfig>, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_sensors().to_vec())
+}
+
+// 设置传感器配置, 立即读取一次
+#[tauri::command]
+pub fn set_robot_sensors(id: u32, sensors: Vec<SensorConfig>, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<SensorReading>, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let robot = robots.get_mut(id)?;
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    robot.set_sensors(&grid, sensors.clone())?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Sensors { id, sensors });
+    Ok(robot.get_readings().to_vec())
+}
+
+// 获取最近一次传感器读数
+#[tauri::command]
+pub fn get_sensor_readings(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Vec<SensorReading>, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_readings().to_vec())
+}
+
+// 开启|关闭迷雾模式
+#[tauri::command]
+pub fn set_robot_fog(id: u32, enabled: bool, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<BeliefCell>, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let robot = robots.get_mut(id)?;
+    let grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    robot.set_fog(&grid, enabled);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Fog { id, enabled });
+    Ok(robot.take_belief_changes())
+}
+
+// 开启|关闭自动探索
+#[tauri::command]
+pub fn set_robot_exploring(id: u32, enabled: bool, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_exploring(enabled)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Exploring { id, enabled });
+    Ok(())
+}
+
+// 获取认知地图中所有已知格子, 未开启迷雾模式时返回空
+#[tauri::command]
+pub fn get_belief_grid(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Vec<BeliefCell>, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_belief().map(|belief| belief.cells()).unwrap_or_default())
+}
+
+// 开启|关闭机器人之间的局部避障
+#[tauri::command]
+pub fn set_robot_avoidance(enabled: bool, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.set_avoidance(enabled);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Avoidance { enabled });
+    Ok(())
+}
+
+// 多个机器人同时规划, 返回每个机器人带时间的路径, solver 默认为 cbs
+#[tauri::command]
+pub fn plan_robots(goals: Vec<RobotGoal>, solver: Option<MapfSolver>, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<Vec<TimedPath>, String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let solver = solver.unwrap_or_default();
+    let paths = robots.plan_group(&mut grid, &goals, solver)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Plan { goals, solver });
+    Ok(paths)
+}
+
+// 获取机器人运动学限制
+#[tauri::command]
+pub fn get_robot_kinematics(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Kinematics, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_kinematics())
+}
+
+// 设置机器人运动学限制(加速度、减速度、角速度)
+#[tauri::command]
+pub fn set_robot_kinematics(id: u32, kinematics: Kinematics, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_kinematics(kinematics)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Kinematics { id, kinematics });
+    Ok(())
+}
+
+// 获取机器人运动模型
+#[tauri::command]
+pub fn get_robot_motion(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<MotionModel, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_motion_model())
+}
+
+// 设置机器人运动模型(holonomic | differential | ackermann), 正在移动时重新规划
+#[tauri::command]
+pub fn set_robot_motion(id: u32, motion: MotionModel, robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    robots.get_mut(id)?.set_motion_model(&mut grid, motion)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Motion { id, motion });
+    Ok(())
+}
+
+// 获取机器人路径跟踪控制器
+#[tauri::command]
+pub fn get_robot_tracking(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Tracking, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_tracking())
+}
+
+// 设置机器人路径跟踪控制器(direct | pursuit | stanley)
+#[tauri::command]
+pub fn set_robot_tracking(id: u32, tracking: Tracking, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_tracking(tracking)?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Tracking { id, tracking });
+    Ok(())
+}
+
+/**
+  启动模拟线程, 按固定步长推进所有机器人, 每次推进后推送 `simulation-snapshot`
+  不同时持有 simulation 和 robots 的锁, 与命令一致, 按 robots → grid → scripts → recorder 的顺序加锁, 等待脚本时不持有 robots、grid 的锁
+*/
+pub fn start_simulation(app: AppHandle) {
+    thread::spawn(move || {
+        let interval = Duration::from_secs_f32(TICK_DELTA);
+        let mut last = Instant::now();
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(last).as_secs_f32();
+            last = now;
+
+            if let Err(err) = run_simulation(&app, elapsed) {
+                error!("simulation stopped: {}", err);
+                break;
+            }
+
+            thread::sleep(interval.saturating_sub(now.elapsed()));
+        }
+    });
+}
+
+// 推进一次模拟
+fn run_simulation(app: &AppHandle, elapsed: f32) -> Result<(), String> {
+    let (ticks, status) = {
+        let simulation = app.state::<Mutex<Simulation>>();
+        let mut simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+        (simulation.advance(elapsed), simulation.status())
+    };
+
+    if ticks == 0 {
+        return Ok(());
+    }
+
+    let robots = app.state::<Mutex<RobotRegistry>>();
+    let grid = app.state::<Mutex<Grid>>();
+    let recorder = app.state::<Mutex<Recorder>>();
+    for _ in 0..ticks {
+        tick_scripts(app, TICK_DELTA, None)?;
+        let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+        let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+        let mut recorder = recorder.lock().map_err(|_| "Mutex recorder poisoned")?;
+        let result = robots.tick(&mut grid, TICK_DELTA);
+        emit_tick(app, &result);
+        recorder.record_frame(SessionInput::Tick { delta: TICK_DELTA }, result.states.clone());
+    }
+
+    let snapshot = SimulationSnapshot {
+        tick: status.tick,
+        time: status.time,
+        robots: robots.lock().map_err(|_| "Mutex robots poisoned")?.states(),
+    };
+    let _ = app.emit("simulation-snapshot", &snapshot);
+    Ok(())
+}
+
+// 获取模拟状态
+#[tauri::command]
+pub fn get_simulation(simulation: State<Mutex<Simulation>>) -> Result<SimulationStatus, String> {
+    let simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+    Ok(simulation.status())
+}
+
+// 暂停模拟
+#[tauri::command]
+pub fn pause_simulation(simulation: State<Mutex<Simulation>>) -> Result<SimulationStatus, String> {
+    let mut simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+    simulation.pause();
+    Ok(simulation.status())
+}
+
+// 继续模拟
+#[tauri::command]
+pub fn resume_simulation(simulation: State<Mutex<Simulation>>) -> Result<SimulationStatus, String> {
+    let mut simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+    simulation.resume();
+    Ok(simulation.status())
+}
+
+// 暂停时单步执行 count 个 tick, 默认 1 个
+#[tauri::command]
+pub fn step_simulation(count: Option<u32>, simulation: State<Mutex<Simulation>>) -> Result<SimulationStatus, String> {
+    let mut simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+    simulation.step(count.unwrap_or(1))?;
+    Ok(simulation.status())
+}
+
+// 设置模拟速度倍率
+#[tauri::command]
+pub fn set_simulation_time_scale(time_scale: f32, simulation: State<Mutex<Simulation>>) -> Result<SimulationStatus, String> {
+    let mut simulation = simulation.lock().map_err(|_| "Mutex simulation poisoned")?;
+    simulation.set_time_scale(time_scale)?;
+    Ok(simulation.status())
+}
+
+// 开始录制, 当前世界会按录制的初始状态重建一次
+#[tauri::command]
+pub fn start_recording(robots: State<Mutex<RobotRegistry>>, grid: State<Mutex<Grid>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    let mut grid = grid.lock().map_err(|_| "Mutex grid poisoned")?;
+    let mut recorder = recorder.lock().map_err(|_| "Mutex recorder poisoned")?;
+    recorder.start(&mut grid, &mut robots)
+}
+
+// 结束录制并保存到文件, 返回录制的帧数
+#[tauri::command]
+pub fn stop_recording(path: PathBuf, app: AppHandle, recorder: State<Mutex<Recorder>>) -> Result<usize, String> {
+    let path = data_path(&app, path)?;
+    let log = recorder.lock().map_err(|_| "Mutex recorder poisoned")?.stop()?;
+    log.save(&path)?;
+    Ok(log.frames.len())
+}
+
+// 加载录制文件用于回放, 回放在独立的世界中进行
+#[tauri::command]
+pub fn load_replay(path: PathBuf, app: AppHandle, replay: State<Mutex<Option<Replay>>>) -> Result<ReplayStatus, String> {
+    let path = data_path(&app, path)?;
+    let loaded = Replay::new(SessionLog::load(&path)?)?;
+    let status = loaded.status();
+    *replay.lock().map_err(|_| "Mutex replay poisoned")? = Some(loaded);
+    Ok(status)
+}
+
+// 回放跳到第 frame 帧, 返回所有机器人的状态
+#[tauri::command]
+pub fn seek_replay(frame: usize, replay: State<Mutex<Option<Replay>>>) -> Result<Vec<RobotState>, String> {
+    let mut replay = replay.lock().map_err(|_| "Mutex replay poisoned")?;
+    replay.as_mut().ok_or("no replay loaded")?.seek(frame)
+}
+
+// 从头回放并与录制的状态比较, 返回第一处不一致, 一致时返回空
+#[tauri::command]
+pub fn verify_replay(replay: State<Mutex<Option<Replay>>>) -> Result<Option<Divergence>, String> {
+    let mut replay = replay.lock().map_err(|_| "Mutex replay poisoned")?;
+    replay.as_mut().ok_or("no replay loaded")?.verify()
+}
+
+// 设置机器人行为树, blackboard 为黑板初始值
+#[tauri::command]
+pub fn set_robot_behaviour(id: u32, tree: BehaviourNode, blackboard: Option<BTreeMap<String, serde_json::Value>>, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let behaviour = Behaviour::new(tree, blackboard.unwrap_or_default())?;
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_behaviour(Some(behaviour.clone()));
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Behaviour { id, behaviour: Some(behaviour) });
+    Ok(())
+}
+
+// 从 JSON 文件加载机器人行为树
+#[tauri::command]
+pub fn load_robot_behaviour(id: u32, path: PathBuf, app: AppHandle, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let path = data_path(&app, path)?;
+    let behaviour = Behaviour::from_file(&path)?;
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_behaviour(Some(behaviour.clone()));
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Behaviour { id, behaviour: Some(behaviour) });
+    Ok(())
+}
+
+// 停止机器人行为树
+#[tauri::command]
+pub fn clear_robot_behaviour(id: u32, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_behaviour(None);
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Behaviour { id, behaviour: None });
+    Ok(())
+}
+
+// 获取机器人行为树(含运行状态和黑板)
+#[tauri::command]
+pub fn get_robot_behaviour(id: u32, robots: State<Mutex<RobotRegistry>>) -> Result<Option<Behaviour>, String> {
+    let robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    Ok(robots.get(id)?.get_behaviour().cloned())
+}
+
+// 写入机器人行为树的黑板
+#[tauri::command]
+pub fn set_robot_blackboard(id: u32, key: String, value: serde_json::Value, robots: State<Mutex<RobotRegistry>>, recorder: State<Mutex<Recorder>>) -> Result<(), String> {
+    let mut robots = robots.lock().map_err(|_| "Mutex robots poisoned")?;
+    robots.get_mut(id)?.set_blackboard(key.clone(), value.clone())?;
+    recorder.lock().map_err(|_| "Mutex recorder poisoned")?.record(SessionInput::Blackboard { id, key, value });
+    Ok(())
+}
+
+// 在机器人上运行 Rhai 脚本, 替换正在运行的脚本, 语法错误直接返回, 运行中的错误通过 `script` 事件推送
+#[tauri::command]
+pub fn run_robot_script(id: u32, source: String, robots: State<Mutex<RobotRegistry>>, scripts: State<Mutex<ScriptHost>>) -> Result<(), String> {
+    robots.lock().map_err(|_| "Mutex robots poisoned")?.get(id)?;
+    scripts.lock().map_err(|_| "Mutex scripts poisoned")?.start(id, &source)
+}
+
+// 从文件加载并运行机器人脚本
+#[tauri::command]
+pub fn load_robot_script(id: u32, path: PathBuf, app: AppHandle, robots: State<Mutex<RobotRegistry>>, scripts: State<Mutex<ScriptHost>>) -> Result<(), String> {
+    let path = data_path(&app, path)?;
+    robots.lock().map_err(|_| "Mutex robots poisoned")?.get(id)?;
+    scripts.lock().map_err(|_| "Mutex scripts poisoned")?.load(id, &path)
+}
+
+// 停止机器人脚本, 已经开始的移动、动作不会撤销
+#[tauri::command]
+pub fn stop_robot_script(id: u32, scripts: State<Mutex<ScriptHost>>) -> Result<(), String> {
+    scripts.lock().map_err(|_| "Mutex scripts poisoned")?.stop(id)
+}
+
+// 获取正在运行脚本的机器人
+#[tauri::command]
+pub fn get_robot_scripts(scripts: State<Mutex<ScriptHost>>) -> Result<Vec<u32>, String> {
+    Ok(scripts.lock().map_err(|_| "Mutex scripts poisoned")?.ids())
 }
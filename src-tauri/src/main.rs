@@ -9,9 +9,20 @@ mod system;
 mod module;
 
 use crate::module::grid::Grid;
-use crate::module::robot::Robot;
+use crate::module::robots::RobotRegistry;
+use crate::module::script::ScriptHost;
+use crate::module::session::{Recorder, Replay};
+use crate::module::simulation::Simulation;
 use crate::system::tray::Tray;
-use exports::{clear_obstacles, clear_robot_path, generate_pillars, generate_rocks, get_init_props, get_robot_point, grid_to_world, on_update_robot_position, set_place_flag, set_robot_action, set_robot_emote, set_robot_target, world_to_grid};
+use exports::{
+    add_marker, add_obstacle, add_shaped_obstacle, add_zone, can_robot_see_marker, cell_to_world, clear_obstacles, clear_robot_behaviour, clear_robot_path, despawn_robot, generate_obstacles, generate_pillars, generate_rocks, get_belief_grid,
+    get_coordinate_system, get_dynamic_obstacles, get_init_props, get_markers, get_obstacle_at, get_obstacle_types, get_obstacles, get_occupied_cells, get_robot_behaviour, get_robot_kinematics, get_robot_motion, get_robot_point, get_robot_scripts,
+    get_robot_sensors, get_robot_tracking, get_robots, get_sensor_readings, get_simulation, get_zones, has_line_of_sight, load_obstacle_types, load_replay, load_robot_behaviour, load_robot_script, load_world, move_marker, move_obstacle,
+    on_update_robot_position, pause_simulation, plan_robots, raycast_grid, remove_marker, remove_obstacle, remove_zone, resize_obstacle, resume_simulation, run_robot_script, save_world, seek_replay, set_obstacle_dynamic, set_obstacle_open,
+    set_place_flag, set_robot_action, set_robot_avoidance, set_robot_behaviour, set_robot_blackboard, set_robot_emote, set_robot_exploring, set_robot_fog, set_robot_kinematics, set_robot_motion, set_robot_sensors, set_robot_target,
+    set_robot_target_to_marker, set_robot_tracking, set_simulation_time_scale, spawn_robot, start_recording, start_simulation, step_simulation, stop_recording, stop_robot_script, tick_robots, verify_replay, world_to_grid,
+};
+use log::error;
 use std::sync::Mutex;
 
 // const PROJECT_NAME: &str = "n-3d";
@@ -22,19 +33,18 @@ pub const HEIGHT: f32 = 200f32;
 pub const CHARACTER_OCCUPY_WIDTH: f32 = 2f32;
 pub const CHARACTER_OCCUPY_HEIGHT: f32 = 2f32;
 
-// 柱子占用 2 * 2 格
-pub const PILLAR_SIZE: usize = 2;
-
-pub const ROCK_SIZE_WITH: usize = 8;
-
-pub const ROCK_SIZE_DEPTH: usize = 4;
-
 pub const SPEED: f32 = 2.0f32;
 
 // 日志目录: /Users/xxx/Library/Logs/n-3d
 // 程序配置目录: /Users/xxx/Library/Application Support/n-3d
 
 fn main() {
+    let mut grid = Grid::new(WIDTH as usize, HEIGHT as usize);
+    let mut robots = RobotRegistry::new();
+    if let Err(err) = robots.spawn(&mut grid, 0.0, 0.0, SPEED) {
+        error!("spawn robot error: {}", err);
+    }
+
     // tauri
     tauri::Builder::default()
         // .plugin(tauri_plugin_window::init())
@@ -47,13 +57,20 @@ fn main() {
 
             // let app_handle = app.handle();
 
+            // 固定步长的模拟线程
+            start_simulation(app.handle().clone());
+
             Ok(())
         })
-        .manage(Mutex::new(Robot::new(0.0, 0.0, SPEED))) // 初始在中心
-        .manage(Mutex::new(Grid::new(WIDTH as usize, HEIGHT as usize))) // 初始化 Grid
+        .manage(Mutex::new(robots)) // 初始一个机器人, 在中心
+        .manage(Mutex::new(grid)) // 初始化 Grid
+        .manage(Mutex::new(Simulation::new())) // 模拟调度
+        .manage(Mutex::new(ScriptHost::new())) // 机器人脚本
+        .manage(Mutex::new(Recorder::new())) // 录制
+        .manage(Mutex::new(None::<Replay>)) // 回放
         .invoke_handler(tauri::generate_handler![
             world_to_grid,
-            grid_to_world,
+            get_coordinate_system,
             set_robot_target,
             on_update_robot_position,
             set_robot_action,
@@ -64,7 +81,72 @@ fn main() {
             generate_rocks,
             generate_pillars,
             clear_robot_path,
-            clear_obstacles
+            clear_obstacles,
+            get_obstacles,
+            add_obstacle,
+            remove_obstacle,
+            move_obstacle,
+            resize_obstacle,
+            get_obstacle_at,
+            add_shaped_obstacle,
+            generate_obstacles,
+            get_obstacle_types,
+            load_obstacle_types,
+            set_obstacle_dynamic,
+            set_obstacle_open,
+            get_dynamic_obstacles,
+            get_occupied_cells,
+            add_marker,
+            move_marker,
+            remove_marker,
+            get_markers,
+            set_robot_target_to_marker,
+            add_zone,
+            remove_zone,
+            get_zones,
+            save_world,
+            load_world,
+            raycast_grid,
+            has_line_of_sight,
+            can_robot_see_marker,
+            get_robot_sensors,
+            set_robot_sensors,
+            get_sensor_readings,
+            set_robot_fog,
+            set_robot_exploring,
+            get_belief_grid,
+            spawn_robot,
+            despawn_robot,
+            get_robots,
+            tick_robots,
+            set_robot_avoidance,
+            plan_robots,
+            get_robot_kinematics,
+            set_robot_kinematics,
+            get_robot_motion,
+            set_robot_motion,
+            get_robot_tracking,
+            set_robot_tracking,
+            get_simulation,
+            pause_simulation,
+            resume_simulation,
+            step_simulation,
+            set_simulation_time_scale,
+            start_recording,
+            stop_recording,
+            load_replay,
+            seek_replay,
+            verify_replay,
+            set_robot_behaviour,
+            load_robot_behaviour,
+            clear_robot_behaviour,
+            get_robot_behaviour,
+            set_robot_blackboard,
+            run_robot_script,
+            load_robot_script,
+            stop_robot_script,
+            get_robot_scripts,
+            cell_to_world
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
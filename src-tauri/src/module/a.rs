@@ -2,11 +2,17 @@
   A* 算法, 查找最短路径
 */
 
-use crate::module::grid::{Grid, GridPoint, ThreeGrid};
+use crate::module::grid::{Grid, GridBounds, GridPoint, ThreeGrid};
 use crate::module::robot::Vec3;
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet};
 
+// 世界不限大小, 限制最多扩展的节点数, 防止终点不可达时无限搜索
+const MAX_EXPANDED_NODES: usize = 200_000;
+
+// 寻路范围在有数据的范围外扩展的格子数, 见 `Grid::search_bounds`
+pub const SEARCH_MARGIN: i32 = 8;
+
 #[derive(Debug)]
 struct Node {
     point: GridPoint,
@@ -45,7 +51,7 @@ fn heuristic(a: &GridPoint, b: &GridPoint) -> f64 {
 }
 
 /// 获取 8 方向邻居(含障碍检测 + 防止对角穿墙)
-fn get_neighbors(grid: &Grid, p: GridPoint) -> Vec<(GridPoint, f64)> {
+fn get_neighbors(grid: &Grid, bounds: &GridBounds, p: GridPoint) -> Vec<(GridPoint, f64)> {
     // (dx, dy, move_cost), 上下左右为 1 格, 对角线为 2 格
     // 对角线: √(1² + 1²) = √2
     let directions = vec![
@@ -63,30 +69,22 @@ fn get_neighbors(grid: &Grid, p: GridPoint) -> Vec<(GridPoint, f64)> {
     for (dx, dz, cost) in directions {
         let nx = p.gx + dx;
         let nz = p.gz + dz;
-
-        // 边界检查
-        if nx < 0 || nz < 0 {
+        if !bounds.contains(nx, nz) {
             continue;
         }
 
-        if nx >= grid.width() as i32 || nz >= grid.height() as i32 {
+        // 目标格子是否 blocked(含禁行区)
+        let cell = grid.get_cell(nx, nz);
+        if cell.occupied || grid.is_blocked(nx, nz) {
             continue;
         }
 
-        // 目标格子是否 blocked
-        let cell = grid.get_cell(nx as usize, nz as usize);
-        if cell.blocked || cell.occupied {
-            continue;
-        }
+        // 减速类障碍物、减速区、高代价区增加代价
+        let cost = cost * grid.cell_cost(nx, nz);
 
         // 对角线防止穿墙
-        if dx != 0 && dz != 0 {
-            let cell1 = grid.get_cell((p.gx + dx) as usize, p.gz as usize);
-            let cell2 = grid.get_cell(p.gx as usize, (p.gz + dz) as usize);
-
-            if cell1.blocked || cell2.blocked {
-                continue;
-            }
+        if dx != 0 && dz != 0 && (grid.is_blocked(p.gx + dx, p.gz) || grid.is_blocked(p.gx, p.gz + dz)) {
+            continue;
         }
 
         neighbors.push((GridPoint { gx: nx, gz: nz }, cost));
@@ -101,22 +99,12 @@ pub fn astar(grid: &Grid, start_world: Vec3, goal_world: Vec3) -> Option<Vec<Thr
     let start = grid.point_to_cell(start_world.x, start_world.z);
     let goal = grid.point_to_cell(goal_world.x, goal_world.z);
 
-    if start.is_none() {
-        return None;
-    }
-
-    if goal.is_none() {
-        return None;
-    }
-
-    let start = start.unwrap();
-    let goal = goal.unwrap();
-
     // 如果终点是障碍，直接返回 None
-    if grid.get_cell(goal.gx as usize, goal.gz as usize).blocked {
+    if grid.is_blocked(goal.gx, goal.gz) {
         return None;
     }
 
+    let bounds = grid.search_bounds(&[start, goal], SEARCH_MARGIN);
     let mut open = BinaryHeap::new();
     let mut came_from: HashMap<GridPoint, GridPoint> = HashMap::new();
     let mut g_score: HashMap<GridPoint, f64> = HashMap::new();
@@ -150,7 +138,7 @@ pub fn astar(grid: &Grid, start_world: Vec3, goal_world: Vec3) -> Option<Vec<Thr
             let mut world_path = Vec::new();
 
             for cell in grid_path {
-                let world = grid.cell_to_point(cell.gx as f32, cell.gz as f32);
+                let world = grid.cell_to_world(&cell);
                 world_path.push(world);
             }
 
@@ -160,11 +148,15 @@ pub fn astar(grid: &Grid, start_world: Vec3, goal_world: Vec3) -> Option<Vec<Thr
         // 加入到 closed
         closed.insert(current.point);
 
+        if closed.len() > MAX_EXPANDED_NODES {
+            return None;
+        }
+
         // 当前最优 g
         let current_g = *g_score.get(&current.point).unwrap();
 
         // 扩展邻居
-        for (neighbor, move_cost) in get_neighbors(grid, current.point) {
+        for (neighbor, move_cost) in get_neighbors(grid, &bounds, current.point) {
             // 判断是否在 closed 中
             if closed.contains(&neighbor) {
                 continue;
@@ -190,3 +182,226 @@ pub fn astar(grid: &Grid, start_world: Vec3, goal_world: Vec3) -> Option<Vec<Thr
 
     None
 }
+
+/// 机器人沿线段移动时占用的格子(2 * 2)都不能阻挡、被其他机器人占用或有额外代价(减速区等)
+/// 每 1/4 格采样一次机器人位置, 与 `Robot::sync_occupancy` 的占用计算一致
+fn footprint_clear(grid: &Grid, from: ThreeGrid, to: ThreeGrid) -> bool {
+    let distance = ((to.x - from.x).powi(2) + (to.z - from.z).powi(2)).sqrt();
+    let samples = (distance / (grid.coords().cell_size * 0.25)).ceil().max(1.0) as usize;
+
+    (0..=samples).all(|i| {
+        let t = i as f32 / samples as f32;
+        let cells = grid.robot_cells(from.x + (to.x - from.x) * t, from.z + (to.z - from.z) * t);
+        cells.iter().all(|p| !grid.get_cell(p.gx, p.gz).occupied && grid.cell_cost(p.gx, p.gz) <= 1.0)
+    })
+}
+
+/// 任意角度路径平滑: 从当前点尽量直连更远的路径点
+/// 直连线段上机器人占用的格子必须畅通(见 `footprint_clear`), 否则保留 A* 的折线
+pub fn smooth_path(grid: &Grid, path: Vec<ThreeGrid>) -> Vec<ThreeGrid> {
+    if path.len() <= 2 {
+        return path;
+    }
+
+    let clear = |from: ThreeGrid, to: ThreeGrid| footprint_clear(grid, from, to);
+
+    let mut result = vec![path[0]];
+    let mut anchor = 0;
+    while anchor < path.len() - 1 {
+        let mut next = anchor + 1;
+        for j in (anchor + 2)..path.len() {
+            if !clear(path[anchor], path[j]) {
+                break;
+            }
+
+            next = j;
+        }
+
+        result.push(path[next]);
+        anchor = next;
+    }
+
+    result
+}
+
+#[derive(Debug)]
+struct TimedNode {
+    point: GridPoint,
+    t: u32, // 时间步
+    g: f64, // 从起点到当前点的代价
+    f: f64, // 总代价 f = g + h
+}
+
+/// 最小堆, f 相同时优先时间更晚(更接近终点)的节点
+impl Ord for TimedNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal).then(self.t.cmp(&other.t))
+    }
+}
+
+impl PartialEq for TimedNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TimedNode {}
+
+impl PartialOrd for TimedNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/**
+  时空 A*: 在 (格子, 时间步) 上搜索, 每一步上下左右移动一格或原地等待
+  - 移动的代价为目标格子的 `Grid::cell_cost`(减速区、代价区), 等待的代价为 1
+  - blocked(from, to, t): 第 t 步从 from 移动到 to(等待时 from == to)是否被禁止(预约表、CBS 约束)
+  - min_arrival: 最早可以停在终点的时间步, 之后不会再有约束占用终点
+  - max_time: 最大时间步, 超过则认为无解
+
+  返回每个时间步所在的格子, 下标即时间步
+*/
+pub fn space_time_astar<F>(grid: &Grid, start: GridPoint, goal: GridPoint, min_arrival: u32, max_time: u32, blocked: F) -> Option<Vec<GridPoint>>
+where
+    F: Fn(GridPoint, GridPoint, u32) -> bool,
+{
+    const ACTIONS: [(i32, i32); 5] = [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)];
+
+    if grid.is_blocked(goal.gx, goal.gz) {
+        return None;
+    }
+
+    let h = |p: &GridPoint| ((p.gx - goal.gx).abs() + (p.gz - goal.gz).abs()) as f64;
+    let bounds = grid.search_bounds(&[start, goal], SEARCH_MARGIN);
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(GridPoint, u32), (GridPoint, f64)> = HashMap::new();
+    let mut closed: HashSet<(GridPoint, u32)> = HashSet::new();
+
+    open.push(TimedNode { point: start, t: 0, g: 0.0, f: h(&start) });
+
+    while let Some(current) = open.pop() {
+        if !closed.insert((current.point, current.t)) {
+            continue;
+        }
+
+        if current.point == goal && current.t >= min_arrival {
+            let mut path = vec![goal];
+            let (mut p, mut t) = (goal, current.t);
+            while t > 0 {
+                p = came_from[&(p, t)].0;
+                t -= 1;
+                path.push(p);
+            }
+
+            path.reverse();
+            return Some(path);
+        }
+
+        if closed.len() > MAX_EXPANDED_NODES {
+            return None;
+        }
+
+        if current.t >= max_time {
+            continue;
+        }
+
+        for (dx, dz) in ACTIONS {
+            let next = GridPoint {
+                gx: current.point.gx + dx,
+                gz: current.point.gz + dz,
+            };
+            let t = current.t + 1;
+            if closed.contains(&(next, t)) {
+                continue;
+            }
+
+            let cost = if dx == 0 && dz == 0 {
+                1.0
+            } else {
+                if !bounds.contains(next.gx, next.gz) || grid.get_cell(next.gx, next.gz).occupied || grid.is_blocked(next.gx, next.gz) {
+                    continue;
+                }
+
+                // 代价不小于 1, 曼哈顿距离仍是可采纳的启发函数
+                grid.cell_cost(next.gx, next.gz).max(1.0)
+            };
+
+            if !cost.is_finite() || blocked(current.point, next, current.t) {
+                continue;
+            }
+
+            let g = current.g + cost;
+            if came_from.get(&(next, t)).is_some_and(|&(_, best)| best <= g) {
+                continue;
+            }
+
+            came_from.insert((next, t), (current.point, g));
+            open.push(TimedNode { point: next, t, g, f: g + h(&next) });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(grid: &Grid, gx: i32, gz: i32) -> Vec3 {
+        let p = grid.cell_to_world(&GridPoint { gx, gz });
+        Vec3 { x: p.x, y: 0.0, z: p.z }
+    }
+
+    // 终点被围住时只在寻路范围内搜索, 不会向外无限扩展
+    #[test]
+    fn enclosed_goal_is_bounded() {
+        let mut grid = Grid::new(20, 20);
+        grid.add_obstacle("rock", 8, 8, Some(5), Some(1)).unwrap();
+        grid.add_obstacle("rock", 8, 12, Some(5), Some(1)).unwrap();
+        grid.add_obstacle("rock", 8, 9, Some(1), Some(3)).unwrap();
+        grid.add_obstacle("rock", 12, 9, Some(1), Some(3)).unwrap();
+
+        let bounds = grid.search_bounds(&[GridPoint { gx: 0, gz: 0 }, GridPoint { gx: 10, gz: 10 }], SEARCH_MARGIN);
+        assert!(bounds.contains(-SEARCH_MARGIN, -SEARCH_MARGIN));
+        assert!(!bounds.contains(bounds.max.gx + 1, 0));
+
+        assert!(astar(&grid, world(&grid, 0, 0), world(&grid, 10, 10)).is_none());
+        assert!(astar(&grid, world(&grid, 0, 0), world(&grid, 15, 15)).is_some());
+    }
+
+    // 中心线畅通但机器人占用的格子会碰到障碍物时不能直连
+    #[test]
+    fn smooth_path_checks_footprint() {
+        let mut grid = Grid::new(20, 20);
+        grid.add_obstacle("rock", 5, 3, Some(1), Some(1)).unwrap();
+
+        let path: Vec<ThreeGrid> = [(1.5, 4.5), (5.5, 6.5), (9.5, 4.5)].iter().map(|(x, z)| grid.coords().cell_f_to_world(*x, *z)).collect();
+        assert_eq!(smooth_path(&grid, path.clone()), path);
+
+        grid.set_occupied(1, &[GridPoint { gx: 3, gz: 7 }], true);
+        let path: Vec<ThreeGrid> = [(1.5, 9.5), (5.5, 11.5), (9.5, 9.5)].iter().map(|(x, z)| grid.coords().cell_f_to_world(*x, *z)).collect();
+        assert_eq!(smooth_path(&grid, path.clone()).len(), 2);
+    }
+
+    // 时空搜索绕开代价区
+    #[test]
+    fn space_time_avoids_cost_zone() {
+        use crate::module::zone::{ZoneEffect, ZoneShape};
+
+        let mut grid = Grid::new(20, 20);
+        let (a, b) = (grid.coords().cell_f_to_world(5.0, 0.0), grid.coords().cell_f_to_world(7.0, 8.0));
+        let shape = ZoneShape::Rect {
+            min_x: a.x.min(b.x),
+            min_z: a.z.min(b.z),
+            max_x: a.x.max(b.x),
+            max_z: a.z.max(b.z),
+        };
+        grid.add_zone("mud", shape, ZoneEffect::Cost { penalty: 10.0 }).unwrap();
+
+        let path = space_time_astar(&grid, GridPoint { gx: 0, gz: 4 }, GridPoint { gx: 12, gz: 4 }, 0, 40, |_, _, _| false).unwrap();
+        assert!(path.iter().all(|p| grid.cell_cost(p.gx, p.gz) <= 1.0));
+        assert_eq!(path.last(), Some(&GridPoint { gx: 12, gz: 4 }));
+    }
+}
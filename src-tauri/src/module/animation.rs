@@ -0,0 +1,210 @@
+/*!
+  机器人动作|表情状态机
+
+  动作(action)是持续的基础状态, 表情(emote)是一次性的, 播放 duration 秒后回到基础动作:
+  ```
+   - 动作切换: death 只能切换到 standing(起身), sitting 只能切换到 standing | idle | death, 其它动作可以任意切换
+   - 表情: death 时不能播放, sitting 时不能 jump, 播放新表情会打断当前表情, none 表示取消
+   - 锁定移动: death | sitting | dance 以及 punch 表情播放期间不移动, 路径保留, 解除后继续(death 会清除路径)
+   - 速度: walking | running 使用固定速度, 其它动作使用机器人生成时的速度
+  ```
+  每次切换产生一个事件, 由 `RobotRegistry` 收集后推送给前端(`animation`)
+*/
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum RobotAction {
+    Idle,
+    Walking,
+    Running,
+    Dance,
+    Death,
+    Sitting,
+    Standing,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum RobotEmote {
+    None,
+    Jump,
+    Yes,
+    No,
+    Wave,
+    Punch,
+    ThumbsUp,
+}
+
+// 动作切换事件
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AnimationTransition {
+    Action { from: RobotAction, to: RobotAction },
+    Emote { from: RobotEmote, to: RobotEmote },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Animation {
+    action: RobotAction,
+    emote: RobotEmote,
+    #[serde(rename = "emoteRemaining")]
+    emote_remaining: f32, // 当前表情剩余的播放时间(秒)
+    #[serde(skip)]
+    transitions: Vec<AnimationTransition>, // 未推送给前端的切换
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            action: RobotAction::Idle,
+            emote: RobotEmote::None,
+            emote_remaining: 0.0,
+            transitions: Vec::new(),
+        }
+    }
+}
+
+impl RobotAction {
+    pub fn parse(action: &str) -> Result<Self, String> {
+        match action {
+            "idle" => Ok(RobotAction::Idle),
+            "walking" => Ok(RobotAction::Walking),
+            "running" => Ok(RobotAction::Running),
+            "dance" => Ok(RobotAction::Dance),
+            "death" => Ok(RobotAction::Death),
+            "sitting" => Ok(RobotAction::Sitting),
+            "standing" => Ok(RobotAction::Standing),
+            _ => Err(format!("unknown action `{}`", action)),
+        }
+    }
+
+    // 是否可以从当前动作切换到 to
+    pub fn can_transition(&self, to: RobotAction) -> bool {
+        match self {
+            RobotAction::Death => to == RobotAction::Standing,
+            RobotAction::Sitting => matches!(to, RobotAction::Standing | RobotAction::Idle | RobotAction::Death),
+            _ => true,
+        }
+    }
+
+    // 该动作下是否锁定移动
+    pub fn locks_movement(&self) -> bool {
+        matches!(self, RobotAction::Death | RobotAction::Sitting | RobotAction::Dance)
+    }
+
+    // 该动作的固定速度, 为空时使用机器人生成时的速度
+    pub fn speed(&self) -> Option<f32> {
+        match self {
+            RobotAction::Walking => Some(2.5),
+            RobotAction::Running => Some(5.0),
+            _ => None,
+        }
+    }
+}
+
+impl RobotEmote {
+    pub fn parse(emote: &str) -> Result<Self, String> {
+        match emote {
+            "none" => Ok(RobotEmote::None),
+            "jump" => Ok(RobotEmote::Jump),
+            "yes" => Ok(RobotEmote::Yes),
+            "no" => Ok(RobotEmote::No),
+            "wave" => Ok(RobotEmote::Wave),
+            "punch" => Ok(RobotEmote::Punch),
+            "thumbsup" => Ok(RobotEmote::ThumbsUp),
+            _ => Err(format!("unknown emote `{}`", emote)),
+        }
+    }
+
+    // 播放时长(秒)
+    pub fn duration(&self) -> f32 {
+        match self {
+            RobotEmote::None => 0.0,
+            RobotEmote::Jump | RobotEmote::Punch => 1.0,
+            RobotEmote::Yes | RobotEmote::No | RobotEmote::ThumbsUp => 1.5,
+            RobotEmote::Wave => 2.0,
+        }
+    }
+
+    pub fn locks_movement(&self) -> bool {
+        *self == RobotEmote::Punch
+    }
+}
+
+impl Animation {
+    pub fn action(&self) -> RobotAction {
+        self.action
+    }
+
+    pub fn emote(&self) -> RobotEmote {
+        self.emote
+    }
+
+    // 切换动作, 不允许的切换返回错误, 动作没有变化时返回 false
+    pub fn set_action(&mut self, action: RobotAction) -> Result<bool, String> {
+        if action == self.action {
+            return Ok(false);
+        }
+
+        if !self.action.can_transition(action) {
+            return Err(format!("cannot change action from {:?} to {:?}", self.action, action));
+        }
+
+        // 死亡打断表情
+        if action == RobotAction::Death {
+            self.finish_emote();
+        }
+
+        self.transitions.push(AnimationTransition::Action { from: self.action, to: action });
+        self.action = action;
+        Ok(true)
+    }
+
+    // 播放表情, none 取消当前表情
+    pub fn set_emote(&mut self, emote: RobotEmote) -> Result<(), String> {
+        if emote == RobotEmote::None {
+            self.finish_emote();
+            return Ok(());
+        }
+
+        if self.action == RobotAction::Death || (self.action == RobotAction::Sitting && emote == RobotEmote::Jump) {
+            return Err(format!("cannot play emote {:?} while {:?}", emote, self.action));
+        }
+
+        self.transitions.push(AnimationTransition::Emote { from: self.emote, to: emote });
+        self.emote = emote;
+        self.emote_remaining = emote.duration();
+        Ok(())
+    }
+
+    // 表情播放结束, 回到基础动作
+    fn finish_emote(&mut self) {
+        if self.emote == RobotEmote::None {
+            return;
+        }
+
+        self.transitions.push(AnimationTransition::Emote { from: self.emote, to: RobotEmote::None });
+        self.emote = RobotEmote::None;
+        self.emote_remaining = 0.0;
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        if self.emote == RobotEmote::None {
+            return;
+        }
+
+        self.emote_remaining -= delta;
+        if self.emote_remaining <= 0.0 {
+            self.finish_emote();
+        }
+    }
+
+    pub fn locks_movement(&self) -> bool {
+        self.action.locks_movement() || self.emote.locks_movement()
+    }
+
+    // 取出未推送的切换
+    pub fn take_transitions(&mut self) -> Vec<AnimationTransition> {
+        std::mem::take(&mut self.transitions)
+    }
+}
@@ -0,0 +1,429 @@
+/*!
+  行为树
+
+  `RobotRegistry` 每次更新机器人之前执行一次行为树, 树从 JSON 加载, 节点按 type 区分:
+  ```
+   - sequence: 依次执行子节点, 一个失败则失败, 全部成功则成功
+   - selector: 依次执行子节点, 一个成功则成功, 全部失败则失败
+   - parallel: 每次执行所有未结束的子节点, 成功数达到 success(默认全部)则成功, 不可能达到时失败
+   - inverter | succeeder: 反转结果 | 总是成功(运行中不变)
+   - repeat: 重复执行子节点 times 次(至少 1 次, 为空时一直重复), 子节点失败则失败, 每次执行最多完成一轮
+   - moveto: 前往标记点(如小红旗), 按 `RobotRegistry::set_target` 避开其它机器人的预约, 到达后成功, 无法到达或被中断时失败
+   - wait: 等待 secs 秒
+   - emote: 播放表情, 播放结束后成功
+   - action: 切换动作, 不允许的切换失败
+   - near | see: 离标记点小于 distance | 能看到标记点时成功, 否则失败
+   - set | check: 写入黑板 | 黑板中的值等于 value 时成功
+  ```
+  标记点名称以 `$` 开头时从黑板中读取, 如 `$patrol` 读取黑板中 patrol 的值
+  组合节点记住运行中的子节点, 下次从该子节点继续, 结束后重置, 提前结束时(如 parallel)已开始的 moveto 停止移动
+  根节点结束后行为树停止, 结果推送给前端(`behaviour`), 需要一直运行的树用 repeat 包裹
+*/
+
+use crate::error::Error;
+use crate::module::animation::RobotEmote;
+use crate::module::grid::{Grid, ThreeGrid};
+use crate::module::marker::Marker;
+use crate::module::raycast::{line_of_sight, RayMask};
+use crate::module::robot::Robot;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// 离目标标记点小于该距离时算作到达
+const ARRIVE_DISTANCE: f32 = 1.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BehaviourStatus {
+    Success,
+    Failure,
+    Running,
+}
+
+// 节点, 带 serde(skip_deserializing) 的字段是运行状态, 不从 JSON 读取
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BehaviourNode {
+    Sequence {
+        children: Vec<BehaviourNode>,
+        #[serde(skip_deserializing)]
+        current: usize,
+    },
+    Selector {
+        children: Vec<BehaviourNode>,
+        #[serde(skip_deserializing)]
+        current: usize,
+    },
+    Parallel {
+        children: Vec<BehaviourNode>,
+        #[serde(default)]
+        success: Option<usize>,
+        #[serde(skip_deserializing)]
+        results: Vec<BehaviourStatus>,
+    },
+    Inverter {
+        child: Box<BehaviourNode>,
+    },
+    Succeeder {
+        child: Box<BehaviourNode>,
+    },
+    Repeat {
+        child: Box<BehaviourNode>,
+        #[serde(default)]
+        times: Option<u32>,
+        #[serde(skip_deserializing)]
+        count: u32,
+    },
+    MoveTo {
+        marker: String,
+        #[serde(skip_deserializing)]
+        started: bool,
+    },
+    Wait {
+        secs: f32,
+        #[serde(skip_deserializing)]
+        elapsed: f32,
+    },
+    Emote {
+        emote: String,
+        #[serde(skip_deserializing)]
+        started: bool,
+    },
+    Action {
+        action: String,
+    },
+    Near {
+        marker: String,
+        distance: f32,
+    },
+    See {
+        marker: String,
+    },
+    Set {
+        key: String,
+        value: Value,
+    },
+    Check {
+        key: String,
+        value: Value,
+    },
+}
+
+// 机器人上运行的行为树
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Behaviour {
+    pub tree: BehaviourNode,
+    #[serde(default)]
+    pub blackboard: BTreeMap<String, Value>,
+    #[serde(default = "running")]
+    pub status: BehaviourStatus,
+}
+
+fn running() -> BehaviourStatus {
+    BehaviourStatus::Running
+}
+
+// 执行节点时可以访问的数据
+pub struct BehaviourContext<'a> {
+    pub robot: &'a mut Robot,
+    pub grid: &'a mut Grid,
+    pub blackboard: &'a mut BTreeMap<String, Value>,
+    pub delta: f32,
+}
+
+impl Behaviour {
+    pub fn new(tree: BehaviourNode, blackboard: BTreeMap<String, Value>) -> Result<Self, String> {
+        tree.validate()?;
+        Ok(Self {
+            tree,
+            blackboard,
+            status: BehaviourStatus::Running,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Self::from_json(&content)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let tree: BehaviourNode = serde_json::from_str(content).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Self::new(tree, BTreeMap::new())
+    }
+
+    // 执行一次, 已结束的树不再执行
+    pub fn tick(&mut self, robot: &mut Robot, grid: &mut Grid, delta: f32) -> BehaviourStatus {
+        if self.status != BehaviourStatus::Running {
+            return self.status;
+        }
+
+        let mut ctx = BehaviourContext {
+            robot,
+            grid,
+            blackboard: &mut self.blackboard,
+            delta,
+        };
+        self.status = self.tree.tick(&mut ctx);
+        self.status
+    }
+}
+
+impl BehaviourNode {
+    // 检查参数
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BehaviourNode::Sequence { children, .. } | BehaviourNode::Selector { children, .. } => children.iter().try_for_each(|child| child.validate()),
+            BehaviourNode::Parallel { children, success, .. } => {
+                if success.is_some_and(|success| success == 0 || success > children.len()) {
+                    return Err(format!("parallel success must be between 1 and {}", children.len()));
+                }
+
+                children.iter().try_for_each(|child| child.validate())
+            }
+            BehaviourNode::Repeat { times: Some(0), .. } => Err("repeat times must be greater than 0".to_string()),
+            BehaviourNode::Inverter { child } | BehaviourNode::Succeeder { child } | BehaviourNode::Repeat { child, .. } => child.validate(),
+            BehaviourNode::Wait { secs, .. } if !(secs.is_finite() && *secs >= 0.0) => Err("wait secs must be finite and not negative".to_string()),
+            BehaviourNode::Near { distance, .. } if !(distance.is_finite() && *distance >= 0.0) => Err("near distance must be finite and not negative".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // 清除运行状态
+    pub fn reset(&mut self) {
+        match self {
+            BehaviourNode::Sequence { children, current } | BehaviourNode::Selector { children, current } => {
+                *current = 0;
+                children.iter_mut().for_each(|child| child.reset());
+            }
+            BehaviourNode::Parallel { children, results, .. } => {
+                results.clear();
+                children.iter_mut().for_each(|child| child.reset());
+            }
+            BehaviourNode::Inverter { child } | BehaviourNode::Succeeder { child } => child.reset(),
+            BehaviourNode::Repeat { child, count, .. } => {
+                *count = 0;
+                child.reset();
+            }
+            BehaviourNode::MoveTo { started, .. } | BehaviourNode::Emote { started, .. } => *started = false,
+            BehaviourNode::Wait { elapsed, .. } => *elapsed = 0.0,
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self, ctx: &mut BehaviourContext) -> BehaviourStatus {
+        let status = self.run(ctx);
+        if status != BehaviourStatus::Running {
+            self.halt(ctx.robot);
+            self.reset();
+        }
+
+        status
+    }
+
+    // 结束时停止仍在运行的节点: 已开始的 moveto 清除路径, 正常结束的子节点已经重置过
+    fn halt(&self, robot: &mut Robot) {
+        match self {
+            BehaviourNode::Sequence { children, .. } | BehaviourNode::Selector { children, .. } | BehaviourNode::Parallel { children, .. } => children.iter().for_each(|child| child.halt(robot)),
+            BehaviourNode::Inverter { child } | BehaviourNode::Succeeder { child } | BehaviourNode::Repeat { child, .. } => child.halt(robot),
+            BehaviourNode::MoveTo { started: true, .. } => robot.clear_path(),
+            _ => {}
+        }
+    }
+
+    fn run(&mut self, ctx: &mut BehaviourContext) -> BehaviourStatus {
+        match self {
+            BehaviourNode::Sequence { children, current } => composite(children, current, ctx, BehaviourStatus::Success),
+            BehaviourNode::Selector { children, current } => composite(children, current, ctx, BehaviourStatus::Failure),
+            BehaviourNode::Parallel { children, success, results } => {
+                results.resize(children.len(), BehaviourStatus::Running);
+                for (child, result) in children.iter_mut().zip(results.iter_mut()) {
+                    if *result == BehaviourStatus::Running {
+                        *result = child.tick(ctx);
+                    }
+                }
+
+                let required = success.unwrap_or(children.len());
+                let succeeded = results.iter().filter(|r| **r == BehaviourStatus::Success).count();
+                let failed = results.iter().filter(|r| **r == BehaviourStatus::Failure).count();
+                if succeeded >= required {
+                    BehaviourStatus::Success
+                } else if children.len() - failed < required {
+                    BehaviourStatus::Failure
+                } else {
+                    BehaviourStatus::Running
+                }
+            }
+            BehaviourNode::Inverter { child } => match child.tick(ctx) {
+                BehaviourStatus::Success => BehaviourStatus::Failure,
+                BehaviourStatus::Failure => BehaviourStatus::Success,
+                BehaviourStatus::Running => BehaviourStatus::Running,
+            },
+            BehaviourNode::Succeeder { child } => match child.tick(ctx) {
+                BehaviourStatus::Running => BehaviourStatus::Running,
+                _ => BehaviourStatus::Success,
+            },
+            BehaviourNode::Repeat { child, times, count } => match child.tick(ctx) {
+                BehaviourStatus::Running => BehaviourStatus::Running,
+                BehaviourStatus::Failure => BehaviourStatus::Failure,
+                BehaviourStatus::Success => {
+                    *count += 1;
+                    if times.is_some_and(|times| *count >= times) {
+                        BehaviourStatus::Success
+                    } else {
+                        BehaviourStatus::Running
+                    }
+                }
+            },
+            BehaviourNode::MoveTo { marker, started } => {
+                let Some(marker) = find_marker(ctx, marker) else {
+                    return BehaviourStatus::Failure;
+                };
+
+                let current = ctx.robot.get_current();
+                let arrived = ((marker.x - current.x).powi(2) + (marker.z - current.z).powi(2)).sqrt() <= ARRIVE_DISTANCE;
+                if !*started {
+                    if arrived {
+                        return BehaviourStatus::Success;
+                    }
+
+                    if ctx.robot.is_movement_locked() {
+                        return BehaviourStatus::Failure;
+                    }
+
+                    // 由 `RobotRegistry` 在执行完行为树后规划, 下次执行时规划失败的机器人没有在移动
+                    ctx.robot.request_target(marker.x, marker.z);
+                    *started = true;
+                    return BehaviourStatus::Running;
+                }
+
+                if ctx.robot.get_moving() {
+                    BehaviourStatus::Running
+                } else if arrived {
+                    BehaviourStatus::Success
+                } else {
+                    BehaviourStatus::Failure
+                }
+            }
+            BehaviourNode::Wait { secs, elapsed } => {
+                *elapsed += ctx.delta;
+                if *elapsed >= *secs {
+                    BehaviourStatus::Success
+                } else {
+                    BehaviourStatus::Running
+                }
+            }
+            BehaviourNode::Emote { emote, started } => {
+                if !*started {
+                    if ctx.robot.set_emote(emote).is_err() {
+                        return BehaviourStatus::Failure;
+                    }
+
+                    *started = true;
+                    return BehaviourStatus::Running;
+                }
+
+                if ctx.robot.get_animation().emote() == RobotEmote::None {
+                    BehaviourStatus::Success
+                } else {
+                    BehaviourStatus::Running
+                }
+            }
+            BehaviourNode::Action { action } => status(ctx.robot.set_action(action).is_ok()),
+            BehaviourNode::Near { marker, distance } => {
+                let current = ctx.robot.get_current();
+                status(find_marker(ctx, marker).is_some_and(|marker| ((marker.x - current.x).powi(2) + (marker.z - current.z).powi(2)).sqrt() <= *distance))
+            }
+            BehaviourNode::See { marker } => {
+                let current = ctx.robot.get_current();
+                let from = ThreeGrid { x: current.x, z: current.z };
+                status(find_marker(ctx, marker).is_some_and(|marker| line_of_sight(ctx.grid, from, ThreeGrid { x: marker.x, z: marker.z }, RayMask::Sight)))
+            }
+            BehaviourNode::Set { key, value } => {
+                ctx.blackboard.insert(key.clone(), value.clone());
+                BehaviourStatus::Success
+            }
+            BehaviourNode::Check { key, value } => status(ctx.blackboard.get(key) == Some(value)),
+        }
+    }
+}
+
+/**
+  sequence | selector: 从 current 开始执行子节点
+  - stop: 子节点返回其它结果时提前结束(sequence 为 success, selector 为 failure)
+*/
+fn composite(children: &mut [BehaviourNode], current: &mut usize, ctx: &mut BehaviourContext, stop: BehaviourStatus) -> BehaviourStatus {
+    while let Some(child) = children.get_mut(*current) {
+        match child.tick(ctx) {
+            BehaviourStatus::Running => return BehaviourStatus::Running,
+            result if result == stop => *current += 1,
+            result => return result,
+        }
+    }
+
+    stop
+}
+
+fn status(success: bool) -> BehaviourStatus {
+    if success {
+        BehaviourStatus::Success
+    } else {
+        BehaviourStatus::Failure
+    }
+}
+
+// 按名称查找标记点, `$` 开头的名称从黑板读取
+fn find_marker(ctx: &BehaviourContext, name: &str) -> Option<Marker> {
+    let name = match name.strip_prefix('$') {
+        Some(key) => ctx.blackboard.get(key)?.as_str()?,
+        None => name,
+    };
+
+    ctx.grid.get_marker_by_name(name).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::robots::RobotRegistry;
+
+    // 运行状态不从 JSON 读取, repeat 至少执行 1 次
+    #[test]
+    fn parse_ignores_state() {
+        let behaviour = Behaviour::from_json(r#"{ "type": "moveto", "marker": "flag", "started": true }"#).unwrap();
+        assert!(matches!(behaviour.tree, BehaviourNode::MoveTo { started: false, .. }));
+
+        let behaviour = Behaviour::from_json(r#"{ "type": "sequence", "current": 5, "children": [{ "type": "wait", "secs": 1, "elapsed": 9 }] }"#).unwrap();
+        assert!(matches!(behaviour.tree, BehaviourNode::Sequence { current: 0, .. }));
+
+        assert!(Behaviour::from_json(r#"{ "type": "repeat", "times": 0, "child": { "type": "action", "action": "idle" } }"#).is_err());
+        assert!(Behaviour::from_json(r#"{ "type": "wait", "secs": -1 }"#).is_err());
+    }
+
+    // 黑板读写, repeat 按次数结束
+    #[test]
+    fn run_sequence() {
+        let mut grid = Grid::new(100, 100);
+        let mut robots = RobotRegistry::new();
+        let id = robots.spawn(&mut grid, 0.0, 0.0, 2.0).unwrap().get_id();
+        let robot = robots.get_mut(id).unwrap();
+        let mut behaviour = Behaviour::from_json(
+            r#"{ "type": "repeat", "times": 2, "child": { "type": "sequence", "children": [
+                { "type": "set", "key": "mode", "value": "patrol" },
+                { "type": "check", "key": "mode", "value": "patrol" },
+                { "type": "wait", "secs": 0.1 }
+            ] } }"#,
+        )
+        .unwrap();
+
+        let mut ticks = 0;
+        while behaviour.tick(robot, &mut grid, 0.05) == BehaviourStatus::Running {
+            ticks += 1;
+            assert!(ticks < 100);
+        }
+
+        assert_eq!(behaviour.status, BehaviourStatus::Success);
+        assert_eq!(behaviour.blackboard.get("mode"), Some(&Value::from("patrol")));
+    }
+}
@@ -0,0 +1,204 @@
+/*!
+  坐标系统一转换
+
+  世界坐标(Three.js X/Z) ↔ 格子坐标(gx/gz) 的所有换算都走这里, 保证同一个点击无论走哪条路径都落在同一个格子上:
+  ```
+   - 世界 → 格子: 统一向下取整(floor), 带一个很小的容差, 避免 0.9999 这种浮点误差落到前一个格子
+   - 格子 → 世界: 按 `CellAnchor` 取格子左上角或格子中心
+   - 坐标轴方向: `AxisDirection` 控制 X/Z 轴是否翻转
+  ```
+*/
+
+use crate::module::grid::{GridPoint, ThreeGrid};
+use serde::{Deserialize, Serialize};
+
+// 浮点误差容差
+const EPSILON: f64 = 1e-4;
+
+// 格子 → 世界坐标时取的锚点
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CellAnchor {
+    Corner, // 格子左上角
+    Center, // 格子中心
+}
+
+// 坐标轴方向
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AxisDirection {
+    Positive, // 世界坐标增大 → 格子坐标增大
+    Negative, // 世界坐标增大 → 格子坐标减小
+}
+
+impl AxisDirection {
+    fn sign(&self) -> f32 {
+        match self {
+            AxisDirection::Positive => 1.0,
+            AxisDirection::Negative => -1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateSystem {
+    #[serde(rename = "originX")]
+    pub origin_x: f32, // 格子 (0, 0) 左上角在世界坐标中的 X
+    #[serde(rename = "originZ")]
+    pub origin_z: f32, // 格子 (0, 0) 左上角在世界坐标中的 Z
+    #[serde(rename = "cellSize")]
+    pub cell_size: f32, // 一个格子的世界尺寸
+    #[serde(rename = "axisX")]
+    pub axis_x: AxisDirection,
+    #[serde(rename = "axisZ")]
+    pub axis_z: AxisDirection,
+    pub anchor: CellAnchor,
+}
+
+impl CoordinateSystem {
+    /*
+      以世界原点为中心的坐标系:
+      - 格子 (0, 0) 在世界 (-width/2, -height/2)
+      - 格子 (width-1, height-1) 在世界 (width/2 - 1, height/2 - 1)
+    */
+    pub fn centered(width: usize, height: usize) -> Self {
+        Self {
+            origin_x: -(width as f32) / 2.0,
+            origin_z: -(height as f32) / 2.0,
+            cell_size: 1.0,
+            axis_x: AxisDirection::Positive,
+            axis_z: AxisDirection::Positive,
+            anchor: CellAnchor::Corner,
+        }
+    }
+
+    // 世界坐标 → 连续格子坐标(不取整), 用 f64 计算, 只保留输入 f32 本身的误差
+    fn world_to_cell_f64(&self, x: f32, z: f32) -> (f64, f64) {
+        let fx = (x as f64 - self.origin_x as f64) * self.axis_x.sign() as f64 / self.cell_size as f64;
+        let fz = (z as f64 - self.origin_z as f64) * self.axis_z.sign() as f64 / self.cell_size as f64;
+        (fx, fz)
+    }
+
+    // 世界坐标 → 连续格子坐标(不取整)
+    pub fn world_to_cell_f(&self, x: f32, z: f32) -> (f32, f32) {
+        let (fx, fz) = self.world_to_cell_f64(x, z);
+        (fx as f32, fz as f32)
+    }
+
+    // 连续格子坐标 → 世界坐标(不考虑锚点, 整数即格子左上角)
+    pub fn cell_f_to_world(&self, gx: f32, gz: f32) -> ThreeGrid {
+        let x = self.origin_x as f64 + gx as f64 * self.cell_size as f64 * self.axis_x.sign() as f64;
+        let z = self.origin_z as f64 + gz as f64 * self.cell_size as f64 * self.axis_z.sign() as f64;
+        ThreeGrid { x: x as f32, z: z as f32 }
+    }
+
+    /**
+      世界坐标 → 格子, 不做越界检查
+      世界坐标是 f32, 世界坐标绝对值不超过 1000 且格子尺寸不小于 0.5 时, 误差小于容差, 格子 → 世界 → 格子 可以精确往返
+    */
+    pub fn world_to_cell(&self, x: f32, z: f32) -> GridPoint {
+        let (fx, fz) = self.world_to_cell_f64(x, z);
+        GridPoint {
+            gx: (fx + EPSILON).floor() as i32,
+            gz: (fz + EPSILON).floor() as i32,
+        }
+    }
+
+    // 格子 → 世界坐标(按锚点)
+    pub fn cell_to_world(&self, point: &GridPoint) -> ThreeGrid {
+        let offset = match self.anchor {
+            CellAnchor::Corner => 0.0,
+            CellAnchor::Center => 0.5,
+        };
+
+        self.cell_f_to_world(point.gx as f32 + offset, point.gz as f32 + offset)
+    }
+
+    // 世界坐标吸附到所在格子的锚点
+    pub fn snap(&self, x: f32, z: f32) -> ThreeGrid {
+        let point = self.world_to_cell(x, z);
+        self.cell_to_world(&point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_system(rng: &mut StdRng) -> CoordinateSystem {
+        let axis = |flip: bool| if flip { AxisDirection::Negative } else { AxisDirection::Positive };
+        CoordinateSystem {
+            origin_x: rng.random_range(-100.0..100.0),
+            origin_z: rng.random_range(-100.0..100.0),
+            cell_size: rng.random_range(0.5..4.0),
+            axis_x: axis(rng.random_bool(0.5)),
+            axis_z: axis(rng.random_bool(0.5)),
+            anchor: if rng.random_bool(0.5) { CellAnchor::Corner } else { CellAnchor::Center },
+        }
+    }
+
+    // 格子 → 世界 → 格子 回到同一个格子
+    #[test]
+    fn cell_round_trip() {
+        let mut rng = StdRng::seed_from_u64(26);
+        for _ in 0..1000 {
+            let coords = random_system(&mut rng);
+            for _ in 0..100 {
+                let cell = GridPoint {
+                    gx: rng.random_range(-225..225),
+                    gz: rng.random_range(-225..225),
+                };
+                let world = coords.cell_to_world(&cell);
+                assert_eq!(coords.world_to_cell(world.x, world.z), cell, "{:?} {:?}", coords, world);
+            }
+        }
+    }
+
+    // 格子内任意一点吸附后仍在同一个格子, 再次吸附不变
+    #[test]
+    fn snap_round_trip() {
+        let mut rng = StdRng::seed_from_u64(27);
+        for _ in 0..1000 {
+            let coords = random_system(&mut rng);
+            for _ in 0..100 {
+                let x = rng.random_range(-1000.0..1000.0);
+                let z = rng.random_range(-1000.0..1000.0);
+                let cell = coords.world_to_cell(x, z);
+                let snapped = coords.snap(x, z);
+                assert_eq!(coords.world_to_cell(snapped.x, snapped.z), cell, "{:?} ({}, {})", coords, x, z);
+                assert_eq!(coords.snap(snapped.x, snapped.z), snapped);
+            }
+        }
+    }
+
+    // 负坐标向下取整, 不向 0 截断; 中心锚点取格子中心
+    #[test]
+    fn negative_coordinates() {
+        let mut coords = CoordinateSystem {
+            origin_x: 0.0,
+            origin_z: 0.0,
+            cell_size: 1.0,
+            axis_x: AxisDirection::Positive,
+            axis_z: AxisDirection::Positive,
+            anchor: CellAnchor::Corner,
+        };
+        assert_eq!(coords.world_to_cell(-0.5, -1.5), GridPoint { gx: -1, gz: -2 });
+        assert_eq!(coords.snap(-0.5, -1.5), ThreeGrid { x: -1.0, z: -2.0 });
+
+        coords.anchor = CellAnchor::Center;
+        assert_eq!(coords.snap(-0.2, -1.9), ThreeGrid { x: -0.5, z: -1.5 });
+
+        let mut rng = StdRng::seed_from_u64(28);
+        for _ in 0..1000 {
+            let coords = random_system(&mut rng);
+            let x = rng.random_range(-1000.0..0.0);
+            let z = rng.random_range(-1000.0..0.0);
+            let (fx, fz) = coords.world_to_cell_f(x, z);
+            let cell = coords.world_to_cell(x, z);
+            assert!(cell.gx as f32 <= fx + 1e-3 && fx < cell.gx as f32 + 1.0, "{:?} ({}, {})", coords, x, z);
+            assert!(cell.gz as f32 <= fz + 1e-3 && fz < cell.gz as f32 + 1.0, "{:?} ({}, {})", coords, x, z);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+/*!
+  动态障碍物: 门、闸门、定时路障
+
+  动态障碍物仍然是普通的 `Obstacle`, 只是额外记录开关状态:
+  ```
+   - 打开: 占用的格子 blocked = false, 但仍记录 obstacle_id, 不允许放置其它东西
+   - 关闭: 占用的格子 blocked = true
+   - schedule: 按模拟时间循环开关, 周期 = open_secs + closed_secs, offset 为相位偏移
+   - 没有 schedule 的只能通过命令开关
+  ```
+  关闭时如果格子上有机器人, 推迟到机器人离开后再关闭
+*/
+
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DynamicSchedule {
+    #[serde(rename = "openSecs")]
+    pub open_secs: f32,
+    #[serde(rename = "closedSecs")]
+    pub closed_secs: f32,
+    #[serde(default)]
+    pub offset: f32,
+}
+
+impl DynamicSchedule {
+    // 时长、偏移为有限的非负数, 周期大于 0
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = [self.open_secs, self.closed_secs, self.offset].iter().all(|v| v.is_finite() && *v >= 0.0);
+        if !valid || self.open_secs + self.closed_secs <= 0.0 {
+            return Err(Error::convert_string("dynamic schedule must be finite and non-negative with a positive period"));
+        }
+
+        Ok(())
+    }
+
+    // 某个时刻是否应该打开
+    pub fn is_open_at(&self, time: f32) -> bool {
+        let period = self.open_secs + self.closed_secs;
+        if period <= 0.0 {
+            return true;
+        }
+
+        (time + self.offset).rem_euclid(period) < self.open_secs
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct DynamicObstacle {
+    #[serde(rename = "obstacleId")]
+    pub obstacle_id: u32,
+    pub open: bool,
+    #[serde(default)]
+    pub schedule: Option<DynamicSchedule>,
+}
+
+// 开关事件, 推送给前端
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct DynamicEvent {
+    #[serde(rename = "obstacleId")]
+    pub obstacle_id: u32,
+    pub open: bool,
+    pub time: f32,
+}
@@ -0,0 +1,156 @@
+/*!
+  迷雾模式: 机器人自己的认知地图
+
+  机器人一开始不知道地图, 只知道初始区域、坐标系、障碍物类型和区域(先验信息, 修改后自动同步), 障碍物要靠传感器发现:
+  ```
+   - unknown: 没观察过, 规划时按空闲处理(乐观假设)
+   - free: 观察过, 没有阻挡
+   - occupied: 观察过, 有阻挡
+  ```
+  认知地图本身也是一个 `Grid`, 已知阻挡的格子 blocked = true, 所以可以直接给 `astar` 使用,
+  发现新的阻挡后, 机器人剩余路径被挡住, 会自动重新规划
+
+  边界(frontier): 已知空闲且与未知格子相邻的格子, 探索时从机器人所在格子沿已知空闲格子 BFS, 前往最近的边界,
+  初始区域内没有边界时探索结束
+*/
+
+use crate::module::grid::{Grid, GridPoint, ThreeGrid};
+use crate::module::raycast::traverse;
+use crate::module::sensor::SensorConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+// 机器人周围直接可见的范围(格子), 防止射线之间漏掉的障碍物挡住机器人
+const NEAR_RADIUS: i32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CellBelief {
+    Unknown,
+    Free,
+    Occupied,
+}
+
+// 认知变化的格子, 推送给前端
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct BeliefCell {
+    pub cell: GridPoint,
+    pub state: CellBelief,
+}
+
+#[derive(Debug, Clone)]
+pub struct BeliefGrid {
+    map: Grid,
+    known: HashSet<GridPoint>,
+}
+
+impl BeliefGrid {
+    pub fn new(grid: &Grid) -> Self {
+        Self { map: grid.blank(), known: HashSet::new() }
+    }
+
+    // 用于规划的地图
+    pub fn map(&self) -> &Grid {
+        &self.map
+    }
+
+    pub fn get(&self, gx: i32, gz: i32) -> CellBelief {
+        if !self.known.contains(&GridPoint { gx, gz }) {
+            CellBelief::Unknown
+        } else if self.map.get_cell(gx, gz).blocked {
+            CellBelief::Occupied
+        } else {
+            CellBelief::Free
+        }
+    }
+
+    // 所有已知格子
+    pub fn cells(&self) -> Vec<BeliefCell> {
+        self.known
+            .iter()
+            .map(|cell| BeliefCell {
+                cell: *cell,
+                state: self.get(cell.gx, cell.gz),
+            })
+            .collect()
+    }
+
+    // 观察一个格子, 同步阻挡和障碍物类型(减速类障碍物影响规划代价), 认知发生变化时记录到 changes(门打开|关闭也会变化)
+    fn observe(&mut self, grid: &Grid, gx: i32, gz: i32, changes: &mut Vec<BeliefCell>) -> bool {
+        let cell = grid.get_cell(gx, gz);
+        let before = self.get(gx, gz);
+
+        self.known.insert(GridPoint { gx, gz });
+        let known = self.map.get_cell(gx, gz);
+        if known.blocked != cell.blocked || known.blocked_type != cell.blocked_type {
+            let mut belief = self.map.get_cell_mut(gx, gz);
+            belief.blocked = cell.blocked;
+            belief.blocked_type = cell.blocked_type;
+        }
+
+        let after = self.get(gx, gz);
+        if before != after {
+            changes.push(BeliefCell { cell: GridPoint { gx, gz }, state: after });
+        }
+
+        cell.blocked
+    }
+
+    // 在 (x, z) 朝 heading 方向用 sensors 观察真实地图, 返回认知变化的格子
+    pub fn sense(&mut self, grid: &Grid, x: f32, z: f32, heading: f32, sensors: &[SensorConfig]) -> Vec<BeliefCell> {
+        let mut changes = Vec::new();
+        self.map.sync_priors(grid);
+
+        let center = grid.point_to_cell(x, z);
+        for dx in -NEAR_RADIUS..=NEAR_RADIUS {
+            for dz in -NEAR_RADIUS..=NEAR_RADIUS {
+                self.observe(grid, center.gx + dx, center.gz + dz, &mut changes);
+            }
+        }
+
+        let origin = ThreeGrid { x, z };
+        for (angle, range) in sensors.iter().flat_map(|sensor| sensor.rays(heading)) {
+            let direction = ThreeGrid { x: angle.sin(), z: angle.cos() };
+            traverse(grid, origin, direction, range, |gx, gz| self.observe(grid, gx, gz, &mut changes));
+        }
+
+        changes
+    }
+
+    // 是否在初始区域内, 探索只在初始区域内进行
+    fn in_area(&self, gx: i32, gz: i32) -> bool {
+        gx >= 0 && gz >= 0 && (gx as usize) < self.map.width() && (gz as usize) < self.map.height()
+    }
+
+    // 已知空闲且可以通过
+    fn is_free(&self, gx: i32, gz: i32) -> bool {
+        self.known.contains(&GridPoint { gx, gz }) && !self.map.is_blocked(gx, gz)
+    }
+
+    // 从 start 沿已知空闲格子 BFS, 返回最近的边界格子, skip 中的格子(无法到达)跳过
+    pub fn nearest_frontier(&self, start: GridPoint, skip: &HashSet<GridPoint>) -> Option<GridPoint> {
+        const DIRS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(point) = queue.pop_front() {
+            let frontier = DIRS.iter().any(|(dx, dz)| {
+                let (nx, nz) = (point.gx + dx, point.gz + dz);
+                self.in_area(nx, nz) && self.get(nx, nz) == CellBelief::Unknown
+            });
+
+            if frontier && point != start && !skip.contains(&point) {
+                return Some(point);
+            }
+
+            for (dx, dz) in DIRS {
+                let next = GridPoint { gx: point.gx + dx, gz: point.gz + dz };
+                if self.in_area(next.gx, next.gz) && self.is_free(next.gx, next.gz) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -2,41 +2,129 @@
 机器人占用格子
 */
 
+use crate::error::Error;
+use crate::module::coord::CoordinateSystem;
+use crate::module::dynamic::{DynamicEvent, DynamicObstacle, DynamicSchedule};
+use crate::module::marker::{Marker, MarkerKind};
+use crate::module::obstacle::{ObstacleDef, ObstacleMovement, ObstacleRegistry};
+use crate::module::shape::ObstacleShape;
+use crate::module::storage::CellStorage;
+use crate::module::world::WorldSnapshot;
+use crate::module::zone::{Zone, ZoneBounds, ZoneEffect, ZoneShape};
 use crate::{CHARACTER_OCCUPY_HEIGHT, CHARACTER_OCCUPY_WIDTH, HEIGHT, WIDTH};
-use rand::Rng;
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
 
-#[derive(Debug)]
+// 每个区块 64 * 64 个格子
+pub const CHUNK_SIZE: i32 = 64;
+
+// 单个障碍物的边长和面积上限, 防止一次写入过多格子
+pub const MAX_OBSTACLE_SIZE: usize = u16::MAX as usize;
+pub const MAX_OBSTACLE_AREA: usize = 1 << 20;
+
+// 随机生成障碍物时, 每个障碍物最多尝试的位置数
+const MAX_PLACE_ATTEMPTS: usize = 1000;
+
+// 格子数据, 由 `CellStorage` 按位打包存储, 读取时解包成该结构
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct GridCell {
-    pub occupied: bool,       // 机器人占用
-    pub has_flag: bool,       // 是否有红旗
-    pub blocked: bool,        // 是否是障碍物（墙等）
-    pub blocked_type: String, // 障碍物类型, 'pillar'
+    pub occupied: bool,           // 机器人占用
+    pub has_flag: bool,           // 是否有红旗等标记点
+    pub blocked: bool,            // 是否是障碍物（墙等）
+    pub blocked_type: Option<u8>, // 障碍物类型在注册表中的编码
+    pub obstacle_id: Option<u32>, // 占用该格子的障碍物 id
+}
+
+// 可写格子, drop 时写回 `CellStorage`
+pub struct GridCellMut<'a> {
+    storage: &'a mut CellStorage,
+    index: usize,
+    cell: GridCell,
+}
+
+impl Deref for GridCellMut<'_> {
+    type Target = GridCell;
+
+    fn deref(&self) -> &GridCell {
+        &self.cell
+    }
+}
+
+impl DerefMut for GridCellMut<'_> {
+    fn deref_mut(&mut self) -> &mut GridCell {
+        &mut self.cell
+    }
+}
+
+impl Drop for GridCellMut<'_> {
+    fn drop(&mut self) {
+        self.storage.set(self.index, &self.cell);
+    }
+}
+
+/*
+  区块坐标:
+  - 格子 (gx, gz) 属于区块 (gx / 64, gz / 64), 向下取整, 负数格子落在负数区块
+  - 区块在第一次写入时才分配, 未分配的区块读取为空格子
+*/
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ChunkKey {
+    pub cx: i32,
+    pub cz: i32,
+}
+
+// 寻路范围(包含边界), 世界不限大小, 寻路只在有数据的范围附近进行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridBounds {
+    pub min: GridPoint,
+    pub max: GridPoint,
 }
 
+impl GridBounds {
+    pub fn contains(&self, gx: i32, gz: i32) -> bool {
+        gx >= self.min.gx && gx <= self.max.gx && gz >= self.min.gz && gz <= self.max.gz
+    }
+}
+
+// width/height 只是初始区域(随机生成障碍物、初始化属性用), 世界本身不限大小
+#[derive(Debug, Clone)]
 pub struct Grid {
     width: usize,
     height: usize,
-    cells: Vec<GridCell>,
+    chunks: HashMap<ChunkKey, CellStorage>,
     obstacles: Vec<Obstacle>,
+    next_obstacle_id: u32,
+    registry: ObstacleRegistry,
+    dynamics: Vec<DynamicObstacle>,
+    markers: Vec<Marker>,
+    next_marker_id: u32,
+    zones: Vec<Zone>,
+    next_zone_id: u32,
+    time: f32, // 模拟时间(秒), 动态障碍物按该时间开关
+    coords: CoordinateSystem,
+    zone_bounds: Vec<ZoneBounds>,            // 与 zones 一一对应
+    prior_revision: u32,                     // 先验信息(障碍物类型、区域)的修改次数, 认知地图据此同步
+    occupants: HashMap<GridPoint, Vec<u32>>, // 占用格子的机器人 id, 最后一个机器人释放时才清除 occupied
 }
 
-// 障碍物
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+// 障碍物, 带 shape 的障碍物 gx/gz/width/depth 为光栅化后的格子包围盒
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Obstacle {
-    pub x: f32,       // 柱子在 grid 中左上角格子坐标 X
-    pub z: f32,       // 柱子在 grid 中左上角格子坐标 Z
+    pub id: u32,      // 障碍物 id, 从 1 开始, 删除后不复用
+    pub x: f32,       // 障碍物中心世界坐标 X
+    pub z: f32,       // 障碍物中心世界坐标 Z
+    pub gx: i32,      // 左上角格子坐标 X
+    pub gz: i32,      // 左上角格子坐标 Z
     pub width: usize, // 占用格子尺寸宽度
     pub depth: usize, // 占用格子尺寸高度
-    pub kind: ObstacleType,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum ObstacleType {
-    Pillar,
-    Rock,
+    pub kind: String, // 障碍物类型 id, 见 `ObstacleRegistry`
+    #[serde(default)]
+    pub shape: Option<ObstacleShape>, // 为空时是轴对齐矩形
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -45,7 +133,7 @@ pub struct GridPoint {
     pub gz: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct ThreeGrid {
     pub x: f32,
     pub z: f32,
@@ -57,12 +145,6 @@ pub struct ThreeGridResultPoint {
     z: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GridResultPoint {
-    x: i32,
-    z: i32,
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GridProps {
     width: usize,
@@ -75,22 +157,30 @@ pub struct GridProps {
 
 impl Grid {
     pub fn new(width: usize, height: usize) -> Self {
-        let total = width * height;
-        let mut cells = Vec::with_capacity(total);
-        for _ in 0..total {
-            cells.push(GridCell {
-                occupied: false,
-                has_flag: false,
-                blocked: false,
-                blocked_type: String::new(),
-            });
-        }
+        info!("Grid created, initial area {} x {}, chunk size {}", width, height, CHUNK_SIZE);
 
-        Self { width, height, cells, obstacles: Vec::new() }
+        Self {
+            width,
+            height,
+            chunks: HashMap::new(),
+            obstacles: Vec::new(),
+            next_obstacle_id: 1,
+            registry: ObstacleRegistry::builtin(),
+            dynamics: Vec::new(),
+            markers: Vec::new(),
+            next_marker_id: 1,
+            zones: Vec::new(),
+            next_zone_id: 1,
+            time: 0.0,
+            coords: CoordinateSystem::centered(width, height),
+            zone_bounds: Vec::new(),
+            prior_revision: 0,
+            occupants: HashMap::new(),
+        }
     }
 
     /**
-    设:
+    区块内的格子下标, 设区块:
     ```
      width = 4
      height = 3
@@ -128,18 +218,62 @@ impl Grid {
      发现: `每一行有 width 个元素`
      所以: `第 z 行的起始 index = z * width`, 再加上 `x`
     */
-    fn index(&self, x: usize, z: usize) -> usize {
-        z * self.width + x
+    fn index(gx: i32, gz: i32) -> (ChunkKey, usize) {
+        let key = ChunkKey {
+            cx: gx.div_euclid(CHUNK_SIZE),
+            cz: gz.div_euclid(CHUNK_SIZE),
+        };
+
+        let x = gx.rem_euclid(CHUNK_SIZE);
+        let z = gz.rem_euclid(CHUNK_SIZE);
+        (key, (z * CHUNK_SIZE + x) as usize)
+    }
+
+    pub fn get_cell(&self, gx: i32, gz: i32) -> GridCell {
+        let (key, i) = Self::index(gx, gz);
+        match self.chunks.get(&key) {
+            Some(chunk) => chunk.get(i),
+            None => GridCell::default(),
+        }
     }
 
-    pub fn get_cell(&self, x: usize, z: usize) -> &GridCell {
-        let i = self.index(x, z);
-        &self.cells[i]
+    // 只保留先验信息(初始区域、坐标系、障碍物类型、区域)的空地图, 用于迷雾模式下的认知地图
+    pub fn blank(&self) -> Grid {
+        let mut grid = Grid::new(self.width, self.height);
+        grid.coords = self.coords;
+        grid.prior_revision = self.prior_revision.wrapping_sub(1);
+        grid.sync_priors(self);
+        grid
     }
 
-    pub fn get_cell_mut(&mut self, x: usize, z: usize) -> &mut GridCell {
-        let i = self.index(x, z);
-        &mut self.cells[i]
+    // 先验信息有变化时从 from 复制, 认知地图每次观察前调用, 区域、障碍物类型在开启迷雾后修改也能同步
+    pub fn sync_priors(&mut self, from: &Grid) {
+        if self.prior_revision == from.prior_revision {
+            return;
+        }
+
+        self.registry = from.registry.clone();
+        self.zones = from.zones.clone();
+        self.zone_bounds = from.zone_bounds.clone();
+        self.next_zone_id = from.next_zone_id;
+        self.prior_revision = from.prior_revision;
+    }
+
+    // 区块内下标 → 格子坐标
+    fn point_of(key: &ChunkKey, index: usize) -> GridPoint {
+        let index = index as i32;
+        GridPoint {
+            gx: key.cx * CHUNK_SIZE + index % CHUNK_SIZE,
+            gz: key.cz * CHUNK_SIZE + index / CHUNK_SIZE,
+        }
+    }
+
+    // 写入时按需分配区块
+    pub fn get_cell_mut(&mut self, gx: i32, gz: i32) -> GridCellMut<'_> {
+        let (key, index) = Self::index(gx, gz);
+        let storage = self.chunks.entry(key).or_insert_with(|| CellStorage::new((CHUNK_SIZE * CHUNK_SIZE) as usize));
+        let cell = storage.get(index);
+        GridCellMut { storage, index, cell }
     }
 
     // 限制 value 在 min_val 和 max_val 之间
@@ -154,26 +288,12 @@ impl Grid {
     }
 
     /*
-      Three.js 坐标系 → Rust 格子坐标
-      - Three.js X/Z: [-100, 100] → Rust 坐标: [-100, 100]
-    */
-    pub fn world_to_grid(grid: &ThreeGrid) -> ThreeGridResultPoint {
-        let gx = grid.x.round();
-        let gz = grid.z.round();
-
-        ThreeGridResultPoint { x: gx, z: gz }
-    }
-
-    /*
-      Rust 格子坐标 → Three.js 坐标系
-      - Rust 坐标: 0..width / 0..height → Three.js X/Z: [-100, 100]
+      Three.js 坐标系 → 吸附到格子锚点的世界坐标
+      - Three.js X/Z: [-100, 100] → 所在格子的锚点(默认左上角)
     */
-    pub fn grid_to_world(grid: &GridPoint) -> GridResultPoint {
-        // 转换坐标，并将中心平移
-        let x = grid.gx;
-        let z = grid.gz;
-
-        GridResultPoint { x, z }
+    pub fn world_to_grid(&self, grid: &ThreeGrid) -> ThreeGridResultPoint {
+        let point = self.coords.snap(grid.x, grid.z);
+        ThreeGridResultPoint { x: point.x, z: point.z }
     }
 
     // 获取初始化坐标
@@ -193,139 +313,839 @@ impl Grid {
         }
     }
 
-    // 映射 -100~100 → 0..width-1/0..height-1
-    pub fn point_to_cell(&self, x: f32, z: f32) -> Option<GridPoint> {
-        let gx = (x + self.width as f32 / 2.0).floor() as i32;
-        let gz = (z + self.height as f32 / 2.0).floor() as i32;
+    // 映射 -100~100 → 0..width-1/0..height-1, 超出初始区域的坐标落在负数或更大的格子上
+    pub fn point_to_cell(&self, x: f32, z: f32) -> GridPoint {
+        self.coords.world_to_cell(x, z)
+    }
+
+    // 映射 0..width-1/0..height-1 → -100~100
+    // 映射到世界坐标 -width/2..width/2, -height/2..height/2, 支持小数格子坐标(如障碍物中心)
+    pub fn cell_to_point(&self, gx: f32, gz: f32) -> ThreeGrid {
+        self.coords.cell_f_to_world(gx, gz)
+    }
+
+    // 格子 → 世界坐标(按坐标系锚点)
+    pub fn cell_to_world(&self, point: &GridPoint) -> ThreeGrid {
+        self.coords.cell_to_world(point)
+    }
+
+    pub fn coords(&self) -> &CoordinateSystem {
+        &self.coords
+    }
 
-        // 越界检查
-        if gx < 0 || gz < 0 {
-            return None;
+    // 添加红旗, 自动命名, 不再清除旧红旗
+    pub fn place_flag(&mut self, x: f32, z: f32) -> bool {
+        // 自动命名, 跳过用户已经使用的名字
+        let name = (self.next_marker_id..).map(|n| format!("flag-{}", n)).find(|name| self.get_marker_by_name(name).is_none()).unwrap_or_default();
+        self.add_marker(&name, MarkerKind::Flag, x, z, serde_json::Value::Null).is_ok()
+    }
+
+    // 添加标记点, 一个格子只能有一个标记点
+    pub fn add_marker(&mut self, name: &str, kind: MarkerKind, x: f32, z: f32, metadata: serde_json::Value) -> Result<Marker, String> {
+        if self.get_marker_by_name(name).is_some() {
+            return Err(Error::convert_string(&format!("marker `{}` already exists", name)));
         }
 
-        if gx >= self.width as i32 || gz >= self.height as i32 {
-            return None;
+        self.check_marker_cell(x, z)?;
+
+        let marker = Marker {
+            id: self.next_marker_id,
+            name: name.to_string(),
+            kind,
+            x,
+            z,
+            metadata,
+        };
+
+        self.next_marker_id += 1;
+        self.set_marker_cell(marker.x, marker.z, true);
+        self.markers.push(marker.clone());
+        Ok(marker)
+    }
+
+    // 移动标记点
+    pub fn move_marker(&mut self, id: u32, x: f32, z: f32) -> Result<Marker, String> {
+        let index = self.marker_index(id)?;
+        let old = self.markers[index].clone();
+
+        let from = self.point_to_cell(old.x, old.z);
+        if self.point_to_cell(x, z) != from {
+            self.check_marker_cell(x, z)?;
         }
 
-        Some(GridPoint { gx, gz })
+        self.set_marker_cell(old.x, old.z, false);
+        self.set_marker_cell(x, z, true);
+
+        let marker = &mut self.markers[index];
+        marker.x = x;
+        marker.z = z;
+        Ok(marker.clone())
     }
 
-    // 映射 0..width-1/0..height-1 → -100~100
-    // 映射到世界坐标 -width/2..width/2, -height/2..height/2
-    pub fn cell_to_point(&self, gx: f32, gz: f32) -> ThreeGrid {
-        let x = gx - self.width as f32 / 2.0;
-        let z = gz - self.height as f32 / 2.0;
+    // 删除标记点
+    pub fn remove_marker(&mut self, id: u32) -> Result<Marker, String> {
+        let index = self.marker_index(id)?;
+        let marker = self.markers.remove(index);
+        self.set_marker_cell(marker.x, marker.z, false);
+        Ok(marker)
+    }
 
-        ThreeGrid { x, z }
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
     }
 
-    // 添加红旗
-    pub fn place_flag(&mut self, x: f32, z: f32) -> bool {
-        if let Some(point) = self.point_to_cell(x, z) {
-            // 清除旧红旗
-            self.clear_flag();
+    pub fn get_marker_by_name(&self, name: &str) -> Option<&Marker> {
+        self.markers.iter().find(|m| m.name == name)
+    }
+
+    fn marker_index(&self, id: u32) -> Result<usize, String> {
+        self.markers.iter().position(|m| m.id == id).ok_or_else(|| Error::convert_string(&format!("marker {} not found", id)))
+    }
+
+    fn check_marker_cell(&self, x: f32, z: f32) -> Result<(), String> {
+        let point = self.point_to_cell(x, z);
+        let cell = self.get_cell(point.gx, point.gz);
+        if cell.has_flag || cell.blocked || cell.occupied {
+            return Err(Error::convert_string(&format!("cell ({}, {}) is not free", point.gx, point.gz)));
+        }
+
+        Ok(())
+    }
 
-            let cell = &mut self.get_cell_mut(point.gx as usize, point.gz as usize);
-            if cell.has_flag || cell.blocked || cell.occupied {
-                return false;
+    fn set_marker_cell(&mut self, x: f32, z: f32, has_flag: bool) {
+        let point = self.point_to_cell(x, z);
+        let mut cell = self.get_cell_mut(point.gx, point.gz);
+        cell.has_flag = has_flag;
+    }
+
+    /**
+      标记|取消机器人 owner 的占用
+      ```
+       - 同一格子可以被多个机器人占用(避让时短暂重叠), 记录每个格子的占用者
+       - 取消时只移除 owner 自己的占用, 没有占用者后才清除 occupied, 不分配区块
+      ```
+    */
+    pub fn set_occupied(&mut self, owner: u32, cells: &[GridPoint], occupied: bool) {
+        for p in cells {
+            let (key, i) = Self::index(p.gx, p.gz);
+            if occupied {
+                let owners = self.occupants.entry(*p).or_default();
+                if !owners.contains(&owner) {
+                    owners.push(owner);
+                }
+                self.chunks.entry(key).or_insert_with(|| CellStorage::new((CHUNK_SIZE * CHUNK_SIZE) as usize)).set_occupied(i, true);
+                continue;
             }
 
-            cell.has_flag = true;
-            return true;
+            let Some(owners) = self.occupants.get_mut(p) else {
+                continue;
+            };
+            owners.retain(|id| *id != owner);
+            if owners.is_empty() {
+                self.occupants.remove(p);
+                if let Some(chunk) = self.chunks.get_mut(&key) {
+                    chunk.set_occupied(i, false);
+                }
+            }
+        }
+    }
+
+    // 机器人中心在 (x, z) 时占用的格子
+    pub fn robot_cells(&self, x: f32, z: f32) -> Vec<GridPoint> {
+        let start = self.point_to_cell(x - CHARACTER_OCCUPY_WIDTH / 2.0, z - CHARACTER_OCCUPY_HEIGHT / 2.0);
+
+        let mut cells = Vec::with_capacity((CHARACTER_OCCUPY_WIDTH * CHARACTER_OCCUPY_HEIGHT) as usize);
+        for dx in 0..CHARACTER_OCCUPY_WIDTH as i32 {
+            for dz in 0..CHARACTER_OCCUPY_HEIGHT as i32 {
+                cells.push(GridPoint { gx: start.gx + dx, gz: start.gz + dz });
+            }
         }
 
-        false
+        cells
+    }
+
+    // 占用格子的机器人
+    pub fn occupants(&self, gx: i32, gz: i32) -> &[u32] {
+        self.occupants.get(&GridPoint { gx, gz }).map_or(&[], |owners| owners.as_slice())
     }
 
-    // 清除红旗
+    /**
+      寻路范围: 初始区域、已分配的区块和 points 的包围盒, 向外扩展 margin 个格子
+      范围外没有障碍物, 不需要绕行, 终点不可达时也不会无限向外搜索
+    */
+    pub fn search_bounds(&self, points: &[GridPoint], margin: i32) -> GridBounds {
+        let mut min = GridPoint { gx: 0, gz: 0 };
+        let mut max = GridPoint {
+            gx: self.width as i32 - 1,
+            gz: self.height as i32 - 1,
+        };
+
+        let chunks = self.chunks.keys().flat_map(|key| {
+            [
+                GridPoint {
+                    gx: key.cx * CHUNK_SIZE,
+                    gz: key.cz * CHUNK_SIZE,
+                },
+                GridPoint {
+                    gx: key.cx * CHUNK_SIZE + CHUNK_SIZE - 1,
+                    gz: key.cz * CHUNK_SIZE + CHUNK_SIZE - 1,
+                },
+            ]
+        });
+        for p in chunks.chain(points.iter().copied()) {
+            min.gx = min.gx.min(p.gx);
+            min.gz = min.gz.min(p.gz);
+            max.gx = max.gx.max(p.gx);
+            max.gz = max.gz.max(p.gz);
+        }
+
+        GridBounds {
+            min: GridPoint { gx: min.gx - margin, gz: min.gz - margin },
+            max: GridPoint { gx: max.gx + margin, gz: max.gz + margin },
+        }
+    }
+
+    // 所有被机器人占用的格子
+    pub fn occupied_cells(&self) -> Vec<GridPoint> {
+        let mut cells = Vec::new();
+        for (key, chunk) in &self.chunks {
+            cells.extend(chunk.occupied().iter_ones().map(|i| Self::point_of(key, i)));
+        }
+
+        cells
+    }
+
+    // 清除所有红旗
     pub fn clear_flag(&mut self) {
-        for cell in &mut self.cells {
-            cell.has_flag = false;
+        let flags: Vec<u32> = self.markers.iter().filter(|m| m.kind == MarkerKind::Flag).map(|m| m.id).collect();
+        for id in flags {
+            let _ = self.remove_marker(id);
         }
     }
 
-    // 清除石头|柱子
-    pub fn clear_obstacle(&mut self, kind: ObstacleType) {
-        // 删除 obstacles 里对应类型
-        self.obstacles.retain(|o| o.kind != kind);
+    // 清除所有标记点
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+        for chunk in self.chunks.values_mut() {
+            chunk.clear_flags();
+        }
+    }
 
-        let kind_str = match kind {
-            ObstacleType::Pillar => "pillar",
-            ObstacleType::Rock => "rock",
-        };
+    // 清除某类障碍物
+    pub fn clear_obstacle(&mut self, kind: &str) {
+        // 删除 obstacles 里对应类型, 并清理格子被占用的标记
+        let (removed, kept): (Vec<Obstacle>, Vec<Obstacle>) = self.obstacles.drain(..).partition(|o| o.kind == kind);
+        self.obstacles = kept;
 
-        // 清理格子被柱子占用的标记
-        for cell in &mut self.cells {
-            if cell.blocked_type == kind_str {
-                cell.blocked = false; // 柱子占用的格子恢复空闲
-                cell.blocked_type.clear();
-            }
+        for obstacle in &removed {
+            self.unstamp_obstacle(obstacle);
         }
+
+        self.retain_dynamics();
     }
 
     // 清除所有障碍物
     pub fn clear_obstacles(&mut self) {
-        self.obstacles.clear();
-
-        for cell in &mut self.cells {
-            if !cell.blocked_type.is_empty() {
-                cell.blocked = false;
-                cell.blocked_type.clear();
-            }
+        let removed: Vec<Obstacle> = self.obstacles.drain(..).collect();
+        for obstacle in &removed {
+            self.unstamp_obstacle(obstacle);
         }
+
+        self.dynamics.clear();
     }
 
-    // 生成障碍物
-    pub fn generate_obstacle(&mut self, nums: usize, width: usize, depth: usize, kind: ObstacleType) -> Vec<Obstacle> {
-        let mut rng = rand::rng();
+    // 生成障碍物, 尺寸取类型定义中的默认尺寸, 相同的 seed 在相同的世界中生成相同的位置, 放不下时返回错误
+    pub fn generate_obstacle(&mut self, nums: usize, kind: &str, seed: u64) -> Result<Vec<Obstacle>, String> {
+        let def = self.registry.get(kind)?;
+        let (width, depth) = (def.width, def.depth);
+        if width >= self.width || depth >= self.height {
+            return Err(Error::convert_string(&format!("obstacle type `{}` does not fit in the grid", kind)));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
         self.clear_obstacle(kind);
 
+        let first_id = self.next_obstacle_id;
         for _ in 0..nums {
             let mut placed = false;
 
-            while !placed {
+            for _ in 0..MAX_PLACE_ATTEMPTS {
                 // 随机选择柱子左上角格子
-                let x = rng.random_range(0..self.width - width);
-                let z = rng.random_range(0..self.height - depth);
+                let x = rng.random_range(0..(self.width - width) as i32);
+                let z = rng.random_range(0..(self.height - depth) as i32);
 
                 // 检查 2 * 2 区域是否空闲
-                let mut can_place = true;
-                for dx in 0..width {
-                    for dz in 0..depth {
-                        let cell = self.get_cell_mut(x + dx, z + dz);
-                        if cell.blocked || cell.occupied || cell.has_flag {
-                            can_place = false;
-                            break;
-                        }
-                    }
+                if self.can_place(&Self::rect_cells(x, z, width, depth), None) {
+                    self.insert_obstacle(kind, x, z, width, depth);
+                    placed = true;
+                    break;
                 }
+            }
+
+            // 找不到空位时撤销本次生成的障碍物
+            if !placed {
+                let ids: Vec<u32> = self.obstacles.iter().map(|o| o.id).filter(|id| *id >= first_id).collect();
+                for id in ids {
+                    self.remove_obstacle(id)?;
+                }
+
+                return Err(Error::convert_string(&format!("no free cells for {} obstacles of type `{}`", nums, kind)));
+            }
+        }
 
-                if can_place {
-                    // 标记柱子占用的格子
-                    for dx in 0..width {
-                        for dz in 0..depth {
-                            let cell = self.get_cell_mut(x + dx, z + dz);
-                            cell.blocked = true;
-                            cell.blocked_type = match kind {
-                                ObstacleType::Pillar => "pillar".to_string(),
-                                ObstacleType::Rock => "rock".to_string(),
-                            };
-                        }
-                    }
+        Ok(self.obstacles.clone())
+    }
 
-                    let center_gx = x as f32 + width as f32 / 2.0;
-                    let center_gz = z as f32 + depth as f32 / 2.0;
+    // 在指定格子添加障碍物, 不传尺寸时取类型定义中的默认尺寸
+    pub fn add_obstacle(&mut self, kind: &str, gx: i32, gz: i32, width: Option<usize>, depth: Option<usize>) -> Result<Obstacle, String> {
+        let def = self.registry.get(kind)?;
+        let width = width.unwrap_or(def.width);
+        let depth = depth.unwrap_or(def.depth);
+        Self::check_obstacle_size(gx, gz, width, depth)?;
 
-                    let point = self.cell_to_point(center_gx, center_gz);
+        if !self.can_place(&Self::rect_cells(gx, gz, width, depth), None) {
+            return Err(Error::convert_string(&format!("cells at ({}, {}) are not free", gx, gz)));
+        }
 
-                    self.obstacles.push(Obstacle { x: point.x, z: point.z, width, depth, kind });
+        Ok(self.insert_obstacle(kind, gx, gz, width, depth))
+    }
 
-                    placed = true;
+    // 添加旋转矩形/圆形/多边形障碍物
+    pub fn add_shaped_obstacle(&mut self, kind: &str, shape: ObstacleShape) -> Result<Obstacle, String> {
+        self.registry.get(kind)?;
+        let obstacle = self.make_shaped_obstacle(self.next_obstacle_id, kind, shape)?;
+        if !self.can_place(&self.footprint(&obstacle), None) {
+            return Err(Error::convert_string("cells covered by the shape are not free"));
+        }
+
+        self.next_obstacle_id += 1;
+        self.stamp_obstacle(&obstacle);
+        self.obstacles.push(obstacle.clone());
+        Ok(obstacle)
+    }
+
+    // 删除障碍物
+    pub fn remove_obstacle(&mut self, id: u32) -> Result<Obstacle, String> {
+        let index = self.obstacle_index(id)?;
+        let obstacle = self.obstacles.remove(index);
+        self.unstamp_obstacle(&obstacle);
+        self.retain_dynamics();
+        Ok(obstacle)
+    }
+
+    // 移动障碍物(左上角移到指定格子), 新位置被占用时保持不动
+    pub fn move_obstacle(&mut self, id: u32, gx: i32, gz: i32) -> Result<Obstacle, String> {
+        let index = self.obstacle_index(id)?;
+        let old = self.obstacles[index].clone();
+
+        let obstacle = match &old.shape {
+            Some(shape) => {
+                let from = self.cell_to_point(old.gx as f32, old.gz as f32);
+                let to = self.cell_to_point(gx as f32, gz as f32);
+                self.make_shaped_obstacle(old.id, &old.kind, shape.translate(to.x - from.x, to.z - from.z))?
+            }
+            None => {
+                Self::check_obstacle_size(gx, gz, old.width, old.depth)?;
+                self.make_obstacle(old.id, &old.kind, gx, gz, old.width, old.depth)
+            }
+        };
+
+        self.replace_obstacle(index, obstacle)
+    }
+
+    // 修改障碍物尺寸, 左上角不变
+    pub fn resize_obstacle(&mut self, id: u32, width: usize, depth: usize) -> Result<Obstacle, String> {
+        let index = self.obstacle_index(id)?;
+        let old = self.obstacles[index].clone();
+        if old.shape.is_some() {
+            return Err(Error::convert_string("only rectangular obstacles can be resized"));
+        }
+        Self::check_obstacle_size(old.gx, old.gz, width, depth)?;
+
+        let obstacle = self.make_obstacle(old.id, &old.kind, old.gx, old.gz, width, depth);
+        self.replace_obstacle(index, obstacle)
+    }
+
+    // 查询覆盖某个世界坐标的障碍物
+    pub fn get_obstacle_at(&self, x: f32, z: f32) -> Option<Obstacle> {
+        let point = self.point_to_cell(x, z);
+        let id = self.get_cell(point.gx, point.gz).obstacle_id?;
+        self.get_obstacle(id).cloned()
+    }
+
+    pub fn get_obstacle(&self, id: u32) -> Option<&Obstacle> {
+        self.obstacles.iter().find(|o| o.id == id)
+    }
+
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn registry(&self) -> &ObstacleRegistry {
+        &self.registry
+    }
+
+    // 替换障碍物类型注册表, 旧类型编码失效, 所以会清除所有障碍物
+    pub fn set_registry(&mut self, registry: ObstacleRegistry) {
+        self.clear_obstacles();
+        self.registry = registry;
+        self.prior_revision = self.prior_revision.wrapping_add(1);
+    }
+
+    // 格子的类型定义
+    pub fn cell_def(&self, cell: &GridCell) -> Option<&ObstacleDef> {
+        self.registry.by_code(cell.blocked_type?)
+    }
+
+    // 格子是否阻挡(障碍物或禁行区)
+    pub fn is_blocked(&self, gx: i32, gz: i32) -> bool {
+        let (key, i) = Self::index(gx, gz);
+        if self.chunks.get(&key).is_some_and(|chunk| chunk.blocked(i)) {
+            return true;
+        }
+
+        let center = self.cell_center(gx, gz);
+        self.zones_at(center.x, center.z).any(|zone| zone.effect == ZoneEffect::Forbidden)
+    }
+
+    // 通过格子的代价倍率, 阻挡为无穷大, 包含减速类障碍物和区域的影响
+    pub fn cell_cost(&self, gx: i32, gz: i32) -> f64 {
+        let cell = self.get_cell(gx, gz);
+        if cell.blocked {
+            return f64::INFINITY;
+        }
+
+        let mut cost = self.cell_def(&cell).map(|def| def.move_cost()).unwrap_or(1.0);
+        let mut penalty = 0.0;
+
+        let center = self.cell_center(gx, gz);
+        for zone in self.zones_at(center.x, center.z) {
+            match zone.effect {
+                ZoneEffect::Forbidden => return f64::INFINITY,
+                ZoneEffect::Slow { factor } => cost /= factor as f64,
+                ZoneEffect::Cost { penalty: p } => penalty += p,
+            }
+        }
+
+        cost + penalty
+    }
+
+    // 某个世界坐标所在格子的速度倍率(减速类障碍物、减速区)
+    pub fn speed_factor(&self, x: f32, z: f32) -> f32 {
+        let point = self.point_to_cell(x, z);
+        let cell = self.get_cell(point.gx, point.gz);
+
+        let mut factor = match self.cell_def(&cell).map(|def| def.movement) {
+            Some(ObstacleMovement::Slow { factor }) => factor,
+            _ => 1.0,
+        };
+
+        let center = self.cell_center(point.gx, point.gz);
+        for zone in self.zones_at(center.x, center.z) {
+            if let ZoneEffect::Slow { factor: f } = zone.effect {
+                factor *= f;
+            }
+        }
+
+        factor
+    }
+
+    // 格子中心的世界坐标
+    pub fn cell_center(&self, gx: i32, gz: i32) -> ThreeGrid {
+        self.coords.cell_f_to_world(gx as f32 + 0.5, gz as f32 + 0.5)
+    }
+
+    // 添加区域
+    pub fn add_zone(&mut self, name: &str, shape: ZoneShape, effect: ZoneEffect) -> Result<Zone, String> {
+        if self.zones.iter().any(|z| z.name == name) {
+            return Err(Error::convert_string(&format!("zone `{}` already exists", name)));
+        }
+
+        shape.validate()?;
+        effect.validate()?;
+
+        let zone = Zone {
+            id: self.next_zone_id,
+            name: name.to_string(),
+            shape,
+            effect,
+        };
+
+        self.next_zone_id += 1;
+        self.zone_bounds.push(zone.shape.bounds());
+        self.zones.push(zone.clone());
+        self.prior_revision = self.prior_revision.wrapping_add(1);
+        Ok(zone)
+    }
+
+    // 删除区域
+    pub fn remove_zone(&mut self, id: u32) -> Result<Zone, String> {
+        let index = self.zones.iter().position(|z| z.id == id).ok_or_else(|| Error::convert_string(&format!("zone {} not found", id)))?;
+        self.zone_bounds.remove(index);
+        self.prior_revision = self.prior_revision.wrapping_add(1);
+        Ok(self.zones.remove(index))
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    // 包含某个世界坐标的区域, 先按包围盒过滤, 不分配内存
+    pub fn zones_at(&self, x: f32, z: f32) -> impl Iterator<Item = &Zone> + '_ {
+        self.zones.iter().zip(&self.zone_bounds).filter(move |(zone, bounds)| bounds.contains(x, z) && zone.shape.contains(x, z)).map(|(zone, _)| zone)
+    }
+
+    // 导出世界存档
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            obstacles: self.obstacles.clone(),
+            dynamics: self.dynamics.clone(),
+            markers: self.markers.clone(),
+            zones: self.zones.clone(),
+        }
+    }
+
+    // 加载世界存档, 替换现有障碍物、标记点和区域, 机器人占用保持不变; 存档无效时不做任何修改
+    pub fn restore(&mut self, snapshot: WorldSnapshot) -> Result<(), String> {
+        let obstacles = self.check_obstacles(snapshot.obstacles)?;
+
+        let mut dynamic_ids = HashSet::new();
+        for dynamic in &snapshot.dynamics {
+            if !obstacles.iter().any(|o| o.id == dynamic.obstacle_id) {
+                return Err(Error::convert_string(&format!("obstacle {} not found", dynamic.obstacle_id)));
+            }
+
+            if !dynamic_ids.insert(dynamic.obstacle_id) {
+                return Err(Error::convert_string(&format!("obstacle {} is dynamic more than once", dynamic.obstacle_id)));
+            }
+
+            if let Some(schedule) = &dynamic.schedule {
+                schedule.validate()?;
+            }
+        }
+
+        for zone in &snapshot.zones {
+            zone.shape.validate()?;
+            zone.effect.validate()?;
+        }
+
+        self.clear_obstacles();
+        self.clear_markers();
+        self.zones.clear();
+        self.zone_bounds.clear();
+
+        // 先恢复开关状态, 标记格子时才能按开关状态设置 blocked
+        self.dynamics = snapshot.dynamics;
+
+        for obstacle in obstacles {
+            self.stamp_obstacle(&obstacle);
+            self.obstacles.push(obstacle);
+        }
+
+        for marker in snapshot.markers {
+            self.set_marker_cell(marker.x, marker.z, true);
+            self.markers.push(marker);
+        }
+
+        self.zone_bounds = snapshot.zones.iter().map(|zone| zone.shape.bounds()).collect();
+        self.zones = snapshot.zones;
+
+        self.next_obstacle_id = self.obstacles.iter().map(|o| o.id).max().unwrap_or(0) + 1;
+        self.next_marker_id = self.markers.iter().map(|m| m.id).max().unwrap_or(0) + 1;
+        self.next_zone_id = self.zones.iter().map(|z| z.id).max().unwrap_or(0) + 1;
+        self.prior_revision = self.prior_revision.wrapping_add(1);
+        Ok(())
+    }
+
+    // 设置为动态障碍物(门等), schedule 为空时只能通过命令开关, 初始为关闭; 需要关闭但格子上有机器人时失败, 保持原状态
+    pub fn make_dynamic(&mut self, id: u32, schedule: Option<DynamicSchedule>) -> Result<DynamicObstacle, String> {
+        self.obstacle_index(id)?;
+        if let Some(schedule) = &schedule {
+            schedule.validate()?;
+        }
+
+        let open = schedule.is_some_and(|schedule| schedule.is_open_at(self.time));
+
+        let previous = self.get_dynamic(id).copied();
+        self.dynamics.retain(|d| d.obstacle_id != id);
+        self.dynamics.push(DynamicObstacle { obstacle_id: id, open, schedule });
+
+        if !self.set_dynamic_state(id, open) {
+            self.dynamics.retain(|d| d.obstacle_id != id);
+            self.dynamics.extend(previous);
+            return Err(Error::convert_string(&format!("obstacle {} is occupied and cannot close", id)));
+        }
+
+        self.get_dynamic(id).copied().ok_or_else(|| Error::convert_string(&format!("obstacle {} is not dynamic", id)))
+    }
+
+    // 手动开关, 关闭时格子上有机器人则失败
+    pub fn set_dynamic_open(&mut self, id: u32, open: bool) -> Result<DynamicObstacle, String> {
+        if self.get_dynamic(id).is_none() {
+            return Err(Error::convert_string(&format!("obstacle {} is not dynamic", id)));
+        }
+
+        if !self.set_dynamic_state(id, open) {
+            return Err(Error::convert_string(&format!("obstacle {} is occupied and cannot close", id)));
+        }
+
+        self.get_dynamic(id).copied().ok_or_else(|| Error::convert_string(&format!("obstacle {} is not dynamic", id)))
+    }
+
+    pub fn get_dynamic(&self, id: u32) -> Option<&DynamicObstacle> {
+        self.dynamics.iter().find(|d| d.obstacle_id == id)
+    }
+
+    pub fn dynamics(&self) -> &[DynamicObstacle] {
+        &self.dynamics
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    // 推进模拟时间, 按 schedule 开关动态障碍物, 返回状态发生变化的事件
+    pub fn advance(&mut self, delta: f32) -> Vec<DynamicEvent> {
+        self.time += delta;
+
+        let mut events = Vec::new();
+        let mut changes = Vec::new();
+        for dynamic in &self.dynamics {
+            if let Some(schedule) = dynamic.schedule {
+                let open = schedule.is_open_at(self.time);
+                if open != dynamic.open {
+                    changes.push((dynamic.obstacle_id, open));
                 }
             }
         }
 
-        self.obstacles.clone()
+        for (id, open) in changes {
+            if self.set_dynamic_state(id, open) {
+                events.push(DynamicEvent { obstacle_id: id, open, time: self.time });
+            }
+        }
+
+        events
+    }
+
+    // 修改动态障碍物开关状态并同步格子 blocked, 关闭时格子被机器人占用则推迟
+    fn set_dynamic_state(&mut self, id: u32, open: bool) -> bool {
+        let obstacle = match self.get_obstacle(id) {
+            Some(obstacle) => obstacle.clone(),
+            None => return false,
+        };
+
+        let cells = self.footprint(&obstacle);
+        if !open && cells.iter().any(|p| self.get_cell(p.gx, p.gz).occupied) {
+            return false;
+        }
+
+        if let Some(dynamic) = self.dynamics.iter_mut().find(|d| d.obstacle_id == id) {
+            dynamic.open = open;
+        }
+
+        let blocks = !open && self.registry.get(&obstacle.kind).map(|def| def.blocks_movement()).unwrap_or(true);
+        for p in cells {
+            let mut cell = self.get_cell_mut(p.gx, p.gz);
+            if cell.obstacle_id == Some(id) {
+                cell.blocked = blocks;
+            }
+        }
+
+        true
+    }
+
+    // 删除已不存在的障碍物对应的动态状态
+    fn retain_dynamics(&mut self) {
+        let ids: Vec<u32> = self.obstacles.iter().map(|o| o.id).collect();
+        self.dynamics.retain(|d| ids.contains(&d.obstacle_id));
+    }
+
+    fn obstacle_index(&self, id: u32) -> Result<usize, String> {
+        self.obstacles.iter().position(|o| o.id == id).ok_or_else(|| Error::convert_string(&format!("obstacle {} not found", id)))
+    }
+
+    /**
+      检查存档中的障碍物, 与添加障碍物的检查一致, 返回按形状重新光栅化后的障碍物
+      ```
+       - 类型已注册, id 大于 0 且不重复
+       - 矩形检查尺寸上限, 带 shape 的检查形状
+       - 障碍物之间不重叠, 不覆盖机器人占用的格子
+      ```
+    */
+    fn check_obstacles(&self, obstacles: Vec<Obstacle>) -> Result<Vec<Obstacle>, String> {
+        let mut ids = HashSet::new();
+        let mut cells = HashSet::new();
+        let mut checked = Vec::with_capacity(obstacles.len());
+        for obstacle in obstacles {
+            self.registry.get(&obstacle.kind)?;
+            if obstacle.id == 0 || !ids.insert(obstacle.id) {
+                return Err(Error::convert_string(&format!("obstacle id {} is invalid or duplicated", obstacle.id)));
+            }
+
+            let obstacle = match obstacle.shape {
+                Some(shape) => self.make_shaped_obstacle(obstacle.id, &obstacle.kind, shape)?,
+                None => {
+                    Self::check_obstacle_size(obstacle.gx, obstacle.gz, obstacle.width, obstacle.depth)?;
+                    self.make_obstacle(obstacle.id, &obstacle.kind, obstacle.gx, obstacle.gz, obstacle.width, obstacle.depth)
+                }
+            };
+
+            for p in self.footprint(&obstacle) {
+                if !cells.insert(p) || self.get_cell(p.gx, p.gz).occupied {
+                    return Err(Error::convert_string(&format!("obstacle {} overlaps at ({}, {})", obstacle.id, p.gx, p.gz)));
+                }
+            }
+
+            checked.push(obstacle);
+        }
+
+        Ok(checked)
+    }
+
+    // 格子是否空闲, ignore 为正在移动的障碍物自身
+    fn can_place(&self, cells: &[GridPoint], ignore: Option<u32>) -> bool {
+        cells.iter().all(|p| {
+            let cell = self.get_cell(p.gx, p.gz);
+            if ignore.is_some() && cell.obstacle_id == ignore {
+                return true;
+            }
+
+            !(cell.blocked || cell.obstacle_id.is_some() || cell.occupied || cell.has_flag)
+        })
+    }
+
+    fn replace_obstacle(&mut self, index: usize, obstacle: Obstacle) -> Result<Obstacle, String> {
+        if !self.can_place(&self.footprint(&obstacle), Some(obstacle.id)) {
+            return Err(Error::convert_string(&format!("cells at ({}, {}) are not free", obstacle.gx, obstacle.gz)));
+        }
+
+        let old = self.obstacles[index].clone();
+        self.unstamp_obstacle(&old);
+        self.stamp_obstacle(&obstacle);
+        self.obstacles[index] = obstacle.clone();
+        Ok(obstacle)
+    }
+
+    fn insert_obstacle(&mut self, kind: &str, gx: i32, gz: i32, width: usize, depth: usize) -> Obstacle {
+        let id = self.next_obstacle_id;
+        self.next_obstacle_id += 1;
+
+        let obstacle = self.make_obstacle(id, kind, gx, gz, width, depth);
+        self.stamp_obstacle(&obstacle);
+        self.obstacles.push(obstacle.clone());
+        obstacle
+    }
+
+    fn make_obstacle(&self, id: u32, kind: &str, gx: i32, gz: i32, width: usize, depth: usize) -> Obstacle {
+        let center_gx = gx as f32 + width as f32 / 2.0;
+        let center_gz = gz as f32 + depth as f32 / 2.0;
+
+        let point = self.cell_to_point(center_gx, center_gz);
+        Obstacle {
+            id,
+            x: point.x,
+            z: point.z,
+            gx,
+            gz,
+            width,
+            depth,
+            kind: kind.to_string(),
+            shape: None,
+        }
+    }
+
+    fn make_shaped_obstacle(&self, id: u32, kind: &str, shape: ObstacleShape) -> Result<Obstacle, String> {
+        shape.validate(&self.coords)?;
+        let cells = shape.rasterize(&self.coords);
+        if cells.is_empty() {
+            return Err(Error::convert_string("obstacle shape does not cover any cell"));
+        }
+
+        let min_gx = cells.iter().map(|p| p.gx).min().unwrap_or(0);
+        let min_gz = cells.iter().map(|p| p.gz).min().unwrap_or(0);
+        let max_gx = cells.iter().map(|p| p.gx).max().unwrap_or(0);
+        let max_gz = cells.iter().map(|p| p.gz).max().unwrap_or(0);
+
+        let center = shape.center();
+        Ok(Obstacle {
+            id,
+            x: center.x,
+            z: center.z,
+            gx: min_gx,
+            gz: min_gz,
+            width: (max_gx - min_gx + 1) as usize,
+            depth: (max_gz - min_gz + 1) as usize,
+            kind: kind.to_string(),
+            shape: Some(shape),
+        })
+    }
+
+    // 矩形障碍物尺寸: 不为 0, 不超过上限, 右下角不溢出
+    fn check_obstacle_size(gx: i32, gz: i32, width: usize, depth: usize) -> Result<(), String> {
+        if width == 0 || depth == 0 {
+            return Err(Error::convert_string("obstacle size must be greater than 0"));
+        }
+
+        if width > MAX_OBSTACLE_SIZE || depth > MAX_OBSTACLE_SIZE || width * depth > MAX_OBSTACLE_AREA {
+            return Err(Error::convert_string(&format!("obstacle size {}x{} exceeds the limit ({} per side, {} cells)", width, depth, MAX_OBSTACLE_SIZE, MAX_OBSTACLE_AREA)));
+        }
+
+        if gx.checked_add(width as i32).is_none() || gz.checked_add(depth as i32).is_none() {
+            return Err(Error::convert_string(&format!("obstacle at ({}, {}) is out of range", gx, gz)));
+        }
+
+        Ok(())
+    }
+
+    fn rect_cells(gx: i32, gz: i32, width: usize, depth: usize) -> Vec<GridPoint> {
+        let mut cells = Vec::with_capacity(width * depth);
+        for dx in 0..width as i32 {
+            for dz in 0..depth as i32 {
+                cells.push(GridPoint { gx: gx + dx, gz: gz + dz });
+            }
+        }
+
+        cells
+    }
+
+    // 障碍物占用的格子
+    pub fn footprint(&self, obstacle: &Obstacle) -> Vec<GridPoint> {
+        match &obstacle.shape {
+            Some(shape) => shape.rasterize(&self.coords),
+            None => Self::rect_cells(obstacle.gx, obstacle.gz, obstacle.width, obstacle.depth),
+        }
+    }
+
+    // 标记障碍物占用的格子, 只减速的类型不标记 blocked
+    fn stamp_obstacle(&mut self, obstacle: &Obstacle) {
+        let code = self.registry.code(&obstacle.kind);
+        let open = self.get_dynamic(obstacle.id).map(|d| d.open).unwrap_or(false);
+        let blocks = !open && self.registry.get(&obstacle.kind).map(|def| def.blocks_movement()).unwrap_or(true);
+        for p in self.footprint(obstacle) {
+            let mut cell = self.get_cell_mut(p.gx, p.gz);
+            cell.blocked = blocks;
+            cell.blocked_type = code;
+            cell.obstacle_id = Some(obstacle.id);
+        }
+    }
+
+    // 清除障碍物占用的格子, 只清除仍属于该障碍物的格子
+    fn unstamp_obstacle(&mut self, obstacle: &Obstacle) {
+        for p in self.footprint(obstacle) {
+            let mut cell = self.get_cell_mut(p.gx, p.gz);
+            if cell.obstacle_id != Some(obstacle.id) {
+                continue;
+            }
+
+            cell.blocked = false;
+            cell.blocked_type = None;
+            cell.obstacle_id = None;
+        }
     }
 
     pub fn width(&self) -> usize {
@@ -0,0 +1,172 @@
+/*!
+  Hybrid A*: 在连续的 (x, z, 朝向) 上搜索, 规划不能原地转向的机器人(ackermann)可以走的路径
+
+  ```
+   - 每次扩展沿圆弧前进 STEP_LENGTH: 最大左转、直行、最大右转, 转弯半径为 min_turn_radius
+   - 状态按 (格子, 朝向分区) 去重, 朝向分为 HEADING_BINS 份, 同一分区只保留代价最小的
+   - 圆弧上每隔 SAMPLE_LENGTH 检查一次, 经过阻挡或被占用的格子则丢弃
+   - 离终点小于 GOAL_TOLERANCE 时结束, 终点朝向不限
+  ```
+  只能前进, 不支持倒车, 启发函数为直线距离
+*/
+
+use crate::module::a::SEARCH_MARGIN;
+use crate::module::grid::{Grid, GridBounds, GridPoint, ThreeGrid};
+use crate::module::kinematics::{wrap_angle, Pose};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::f32::consts::TAU;
+
+// 每次扩展前进的距离, 大于格子对角线, 保证离开当前格子
+const STEP_LENGTH: f32 = 1.5;
+
+// 碰撞检测的采样间隔
+const SAMPLE_LENGTH: f32 = 0.25;
+
+const HEADING_BINS: i32 = 72;
+
+// 到达终点的距离
+const GOAL_TOLERANCE: f32 = 1.0;
+
+// 转弯的代价倍率, 优先直行
+const STEER_PENALTY: f32 = 1.1;
+
+const MAX_EXPANDED_NODES: usize = 100_000;
+
+#[derive(Debug)]
+struct HybridNode {
+    index: usize, // poses 中的下标
+    f: f32,
+}
+
+/// 最小堆
+impl Ord for HybridNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for HybridNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HybridNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.f == other.f
+    }
+}
+
+impl Eq for HybridNode {}
+
+fn key(grid: &Grid, pose: &Pose) -> (GridPoint, i32) {
+    let bin = (wrap_angle(pose.yaw).rem_euclid(TAU) / TAU * HEADING_BINS as f32) as i32 % HEADING_BINS;
+    (grid.point_to_cell(pose.x, pose.z), bin)
+}
+
+// 机器人在 (x, z) 时占用的格子都没有阻挡
+fn is_free(grid: &Grid, bounds: &GridBounds, x: f32, z: f32) -> bool {
+    grid.robot_cells(x, z)
+        .iter()
+        .all(|cell| bounds.contains(cell.gx, cell.gz) && !grid.is_blocked(cell.gx, cell.gz) && !grid.get_cell(cell.gx, cell.gz).occupied)
+}
+
+// 沿曲率 curvature 的圆弧前进 length, 曲率为正时朝向增大
+fn drive(pose: &Pose, curvature: f32, length: f32) -> Pose {
+    if curvature == 0.0 {
+        return Pose {
+            x: pose.x + pose.yaw.sin() * length,
+            z: pose.z + pose.yaw.cos() * length,
+            yaw: pose.yaw,
+        };
+    }
+
+    let yaw = pose.yaw + curvature * length;
+    Pose {
+        x: pose.x + (pose.yaw.cos() - yaw.cos()) / curvature,
+        z: pose.z + (yaw.sin() - pose.yaw.sin()) / curvature,
+        yaw: wrap_angle(yaw),
+    }
+}
+
+// 圆弧是否经过阻挡, 不检查起点
+fn is_arc_free(grid: &Grid, bounds: &GridBounds, pose: &Pose, curvature: f32) -> bool {
+    let samples = (STEP_LENGTH / SAMPLE_LENGTH).ceil() as i32;
+    (1..=samples).all(|i| {
+        let p = drive(pose, curvature, STEP_LENGTH * i as f32 / samples as f32);
+        is_free(grid, bounds, p.x, p.z)
+    })
+}
+
+/// Hybrid A* 主函数, 返回的路径第一个点为起点, 最后一个点为终点
+pub fn hybrid_astar(grid: &Grid, start: Pose, goal: ThreeGrid, min_turn_radius: f32) -> Option<Vec<ThreeGrid>> {
+    let bounds = grid.search_bounds(&[grid.point_to_cell(start.x, start.z), grid.point_to_cell(goal.x, goal.z)], SEARCH_MARGIN);
+    if !is_free(grid, &bounds, goal.x, goal.z) || min_turn_radius <= 0.0 {
+        return None;
+    }
+
+    let h = |pose: &Pose| ((pose.x - goal.x).powi(2) + (pose.z - goal.z).powi(2)).sqrt();
+    let max_curvature = 1.0 / min_turn_radius;
+
+    // 所有展开过的状态: (位姿, 代价, 父节点)
+    let mut poses: Vec<(Pose, f32, Option<usize>)> = vec![(start, 0.0, None)];
+    let mut g_score: HashMap<(GridPoint, i32), f32> = HashMap::new();
+    let mut closed: HashSet<(GridPoint, i32)> = HashSet::new();
+    let mut open = BinaryHeap::new();
+
+    g_score.insert(key(grid, &start), 0.0);
+    open.push(HybridNode { index: 0, f: h(&start) });
+
+    while let Some(current) = open.pop() {
+        let (pose, g, _) = poses[current.index];
+        if !closed.insert(key(grid, &pose)) {
+            continue;
+        }
+
+        if h(&pose) <= GOAL_TOLERANCE {
+            // 回溯路径
+            let mut path = vec![goal];
+            let mut index = Some(current.index);
+            while let Some(i) = index {
+                let (pose, _, parent) = poses[i];
+                path.push(ThreeGrid { x: pose.x, z: pose.z });
+                index = parent;
+            }
+
+            path.reverse();
+            return Some(path);
+        }
+
+        if closed.len() > MAX_EXPANDED_NODES {
+            return None;
+        }
+
+        for curvature in [-max_curvature, 0.0, max_curvature] {
+            if !is_arc_free(grid, &bounds, &pose, curvature) {
+                continue;
+            }
+
+            let next = drive(&pose, curvature, STEP_LENGTH);
+            let next_key = key(grid, &next);
+            if closed.contains(&next_key) {
+                continue;
+            }
+
+            let cost = if curvature == 0.0 { STEP_LENGTH } else { STEP_LENGTH * STEER_PENALTY };
+            let tentative_g = g + cost;
+            if tentative_g >= g_score.get(&next_key).copied().unwrap_or(f32::INFINITY) {
+                continue;
+            }
+
+            g_score.insert(next_key, tentative_g);
+            poses.push((next, tentative_g, Some(current.index)));
+            open.push(HybridNode {
+                index: poses.len() - 1,
+                f: tentative_g + h(&next),
+            });
+        }
+    }
+
+    None
+}
@@ -0,0 +1,116 @@
+/*!
+  机器人运动学限制
+
+  每个机器人单独配置, `Robot::update` 中生效:
+  ```
+   - max_acceleration: 最大加速度(格/秒²), 从静止起步逐渐加速到 speed
+   - max_deceleration: 最大减速度(格/秒²), 离最终路径点小于刹车距离 v² / (2 * a) 时开始减速
+   - max_angular_speed: 最大角速度(弧度/秒), 朝向按该速度平滑转向移动方向
+  ```
+  运动模型:
+  ```
+   - holonomic: 可以朝任意方向移动, 朝向只影响显示(默认)
+   - differential: 差速驱动, 只能沿朝向前进, 可以原地转向, 在路径拐点先停下转向
+   - ackermann: 类汽车, 只能沿朝向前进, 不能原地转向, 转弯半径不小于 min_turn_radius, 用 Hybrid A* 规划路径
+  ```
+*/
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MotionModel {
+    #[default]
+    Holonomic,
+    Differential,
+    Ackermann {
+        #[serde(rename = "minTurnRadius")]
+        min_turn_radius: f32,
+    },
+}
+
+impl MotionModel {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            MotionModel::Ackermann { min_turn_radius } if !(min_turn_radius.is_finite() && *min_turn_radius > 0.0) => Err("min turn radius must be finite and positive".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // 是否可以朝任意方向移动
+    pub fn is_holonomic(&self) -> bool {
+        *self == MotionModel::Holonomic
+    }
+}
+
+// 位置和朝向, 朝向与 Robot 一致: 方向 = (sin, cos)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub x: f32,
+    pub z: f32,
+    pub yaw: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Kinematics {
+    #[serde(rename = "maxAcceleration")]
+    pub max_acceleration: f32,
+    #[serde(rename = "maxDeceleration")]
+    pub max_deceleration: f32,
+    #[serde(rename = "maxAngularSpeed")]
+    pub max_angular_speed: f32,
+}
+
+impl Default for Kinematics {
+    fn default() -> Self {
+        Self {
+            max_acceleration: 4.0,
+            max_deceleration: 4.0,
+            max_angular_speed: TAU,
+        }
+    }
+}
+
+impl Kinematics {
+    pub fn validate(&self) -> Result<(), String> {
+        if [self.max_acceleration, self.max_deceleration, self.max_angular_speed].iter().any(|x| !(x.is_finite() && *x > 0.0)) {
+            return Err("kinematic limits must be finite and positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    /**
+      本帧的速度
+      - speed: 当前速度
+      - max_speed: 最大速度
+      - remaining: 到最终路径点的剩余距离, 按刹车距离限制速度
+    */
+    pub fn next_speed(&self, speed: f32, max_speed: f32, remaining: f32, delta: f32) -> f32 {
+        let braking = (2.0 * self.max_deceleration * remaining).sqrt();
+        let desired = max_speed.min(braking);
+        if desired >= speed {
+            desired.min(speed + self.max_acceleration * delta)
+        } else {
+            desired.max(speed - self.max_deceleration * delta)
+        }
+    }
+
+    // 从 current 朝 target 转向, 本帧最多转 max_angular_speed * delta, 返回新的朝向
+    pub fn turn(&self, current: f32, target: f32, delta: f32) -> f32 {
+        let diff = wrap_angle(target - current);
+        let max_turn = self.max_angular_speed * delta;
+        wrap_angle(current + diff.clamp(-max_turn, max_turn))
+    }
+}
+
+// 角度归一化到 (-π, π]
+pub fn wrap_angle(angle: f32) -> f32 {
+    let angle = (angle + PI).rem_euclid(TAU) - PI;
+    if angle <= -PI {
+        angle + TAU
+    } else {
+        angle
+    }
+}
@@ -0,0 +1,380 @@
+/*!
+  多机器人路径规划(MAPF)
+
+  时间离散为时间步, 每一步机器人上下左右移动一格或原地等待(见 `space_time_astar`), 机器人按 2 * 2 占用:
+  ```
+   - 冲突: 同一时间步两个机器人所在格子的切比雪夫距离 < SEPARATION, 或移动过程中(两步的中点)距离过近
+   - 到达终点后一直停在终点, 之后其它机器人也不能经过
+  ```
+  两种求解方式:
+  ```
+   - cbs: Conflict-Based Search, 每次找到第一个冲突, 分别给两个机器人加约束后重新规划, 按总代价最小搜索, 结果最优
+   - reservation: 按顺序规划, 规划好的路径写入预约表, 后面的机器人避开预约, 更快但不保证有解
+  ```
+  单个机器人设置目标时, 其它正在移动的机器人按剩余路径和速度写入预约表, 见 `RobotRegistry::set_target`
+*/
+
+use crate::module::a::space_time_astar;
+use crate::module::grid::{Grid, GridPoint};
+use crate::{CHARACTER_OCCUPY_HEIGHT, CHARACTER_OCCUPY_WIDTH};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// 两个机器人所在格子的最小切比雪夫距离, 小于该距离 2 * 2 占用会重叠
+const SEPARATION: i32 = CHARACTER_OCCUPY_WIDTH as i32;
+
+// CBS 最多展开的约束树节点
+const MAX_CBS_NODES: usize = 2_000;
+
+// CBS 所有底层搜索最多检查的移动数, 每个约束树节点都要重新规划, 只限制节点数不够
+// 不按耗时限制, 回放时重新规划的结果与录制时一致
+const MAX_CBS_MOVES: usize = 1_000_000;
+
+// 单个机器人最长的额外等待时间步
+const MAX_EXTRA_STEPS: u32 = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MapfSolver {
+    #[default]
+    Cbs,
+    Reservation,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MapfAgent {
+    pub start: GridPoint,
+    pub goal: GridPoint,
+}
+
+// 一步移动, 等待时 from == to
+type Move = (GridPoint, GridPoint);
+
+fn chebyshev(a: GridPoint, b: GridPoint) -> i32 {
+    (a.gx - b.gx).abs().max((a.gz - b.gz).abs())
+}
+
+// 同一时间步的两步移动是否冲突: 终点太近, 或中点太近(对穿、交叉)
+fn moves_conflict(a: Move, b: Move) -> bool {
+    if chebyshev(a.1, b.1) < SEPARATION {
+        return true;
+    }
+
+    // 中点按 2 倍坐标比较
+    let mid_a = GridPoint { gx: a.0.gx + a.1.gx, gz: a.0.gz + a.1.gz };
+    let mid_b = GridPoint { gx: b.0.gx + b.1.gx, gz: b.0.gz + b.1.gz };
+    chebyshev(mid_a, mid_b) < SEPARATION * 2
+}
+
+// 第 t 步的移动, 到达终点后一直等待
+fn move_at(path: &[GridPoint], t: usize) -> Move {
+    let last = path.len() - 1;
+    (path[t.min(last)], path[(t + 1).min(last)])
+}
+
+/**
+  预约表
+  - moves: 每个时间步已预约的移动
+  - parked: 停在终点的机器人, (格子, 开始时间步)
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ReservationTable {
+    moves: HashMap<u32, Vec<Move>>,
+    parked: Vec<(GridPoint, u32)>,
+}
+
+impl ReservationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 预约一条路径, 下标为时间步, park 为 true 时到达后一直占用终点
+    pub fn reserve(&mut self, path: &[GridPoint], park: bool) {
+        for t in 0..path.len().saturating_sub(1) {
+            self.moves.entry(t as u32).or_default().push((path[t], path[t + 1]));
+        }
+
+        if let Some(last) = path.last() {
+            if park {
+                self.parked.push((*last, path.len() as u32 - 1));
+            }
+        }
+    }
+
+    // 第 t 步的移动是否和预约冲突
+    pub fn is_blocked(&self, from: GridPoint, to: GridPoint, t: u32) -> bool {
+        if self.parked.iter().any(|(cell, since)| *since <= t + 1 && chebyshev(*cell, to) < SEPARATION) {
+            return true;
+        }
+
+        self.moves.get(&t).is_some_and(|moves| moves.iter().any(|m| moves_conflict((from, to), *m)))
+    }
+
+    // 停在 goal 的最早时间步, 之后没有预约经过 goal 附近, 已经有机器人停在附近时返回 None
+    pub fn min_arrival(&self, goal: GridPoint) -> Option<u32> {
+        if self.parked.iter().any(|(cell, _)| chebyshev(*cell, goal) < SEPARATION) {
+            return None;
+        }
+
+        Some(self.moves.iter().filter(|(_, moves)| moves.iter().any(|m| moves_conflict((goal, goal), *m))).map(|(t, _)| t + 1).max().unwrap_or(0))
+    }
+
+    // 预约的最后一个时间步
+    pub fn horizon(&self) -> u32 {
+        self.moves.keys().max().map(|t| t + 1).unwrap_or(0)
+    }
+}
+
+// 机器人在 cell 时占用的 2 * 2 格子是否有阻挡或被其它机器人占用, 同 `Robot::sync_occupancy`
+fn is_footprint_blocked(grid: &Grid, cell: GridPoint) -> bool {
+    let gx = cell.gx - (CHARACTER_OCCUPY_WIDTH / 2.0) as i32;
+    let gz = cell.gz - (CHARACTER_OCCUPY_HEIGHT / 2.0) as i32;
+    (0..CHARACTER_OCCUPY_WIDTH as i32).any(|dx| (0..CHARACTER_OCCUPY_HEIGHT as i32).any(|dz| grid.is_blocked(gx + dx, gz + dz) || grid.get_cell(gx + dx, gz + dz).occupied))
+}
+
+// CBS 搜索预算, 用完后所有移动都视为被禁止, 底层搜索很快结束
+struct Budget {
+    moves: Cell<usize>,
+}
+
+impl Budget {
+    fn new() -> Self {
+        Self { moves: Cell::new(0) }
+    }
+
+    // 记录一次移动检查, 返回预算是否已经用完
+    fn spend(&self) -> bool {
+        self.moves.set(self.moves.get() + 1);
+        self.is_exhausted()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.moves.get() >= MAX_CBS_MOVES
+    }
+}
+
+// 单个机器人规划, 最长时间为 min_arrival + 直线距离 + MAX_EXTRA_STEPS
+fn plan_agent<F>(grid: &Grid, agent: &MapfAgent, min_arrival: u32, blocked: F) -> Option<Vec<GridPoint>>
+where
+    F: Fn(GridPoint, GridPoint, u32) -> bool,
+{
+    let distance = ((agent.start.gx - agent.goal.gx).abs() + (agent.start.gz - agent.goal.gz).abs()) as u32;
+    space_time_astar(grid, agent.start, agent.goal, min_arrival, min_arrival + distance + MAX_EXTRA_STEPS, |from, to, t| {
+        (from != to && is_footprint_blocked(grid, to)) || blocked(from, to, t)
+    })
+}
+
+// 起点互相重叠时无解
+fn check_starts(agents: &[MapfAgent]) -> Result<(), String> {
+    for (i, a) in agents.iter().enumerate() {
+        for b in &agents[i + 1..] {
+            if chebyshev(a.start, b.start) < SEPARATION {
+                return Err(format!("robots at ({}, {}) and ({}, {}) overlap", a.start.gx, a.start.gz, b.start.gx, b.start.gz));
+            }
+
+            if chebyshev(a.goal, b.goal) < SEPARATION {
+                return Err(format!("goals ({}, {}) and ({}, {}) are too close", a.goal.gx, a.goal.gz, b.goal.gx, b.goal.gz));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 求解, 返回每个机器人每个时间步所在的格子
+pub fn solve(grid: &Grid, agents: &[MapfAgent], solver: MapfSolver) -> Result<Vec<Vec<GridPoint>>, String> {
+    check_starts(agents)?;
+    match solver {
+        MapfSolver::Cbs => cbs(grid, agents),
+        MapfSolver::Reservation => prioritized(grid, agents, &mut ReservationTable::new()),
+    }
+}
+
+// 避开预约表规划单个机器人, 不写入预约表
+pub fn plan_reserved(grid: &Grid, agent: &MapfAgent, table: &ReservationTable) -> Result<Vec<GridPoint>, String> {
+    let min_arrival = table.min_arrival(agent.goal).ok_or_else(|| format!("goal ({}, {}) is reserved", agent.goal.gx, agent.goal.gz))?;
+    plan_agent(grid, agent, min_arrival, |from, to, t| table.is_blocked(from, to, t)).ok_or_else(|| format!("no path from ({}, {}) to ({}, {})", agent.start.gx, agent.start.gz, agent.goal.gx, agent.goal.gz))
+}
+
+// 按顺序规划, 写入预约表
+pub fn prioritized(grid: &Grid, agents: &[MapfAgent], table: &mut ReservationTable) -> Result<Vec<Vec<GridPoint>>, String> {
+    let mut paths = Vec::with_capacity(agents.len());
+    for agent in agents {
+        let path = plan_reserved(grid, agent, table)?;
+        table.reserve(&path, true);
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+// CBS 约束: 第 t 步不能移动到 to(vertex), 或不能从 from 移动到 to(edge)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Constraint {
+    Vertex { cell: GridPoint, t: u32 },
+    Edge { from: GridPoint, to: GridPoint, t: u32 },
+}
+
+fn is_constrained(constraints: &HashSet<Constraint>, from: GridPoint, to: GridPoint, t: u32) -> bool {
+    constraints.contains(&Constraint::Vertex { cell: to, t: t + 1 }) || constraints.contains(&Constraint::Edge { from, to, t })
+}
+
+fn plan_constrained(grid: &Grid, agent: &MapfAgent, constraints: &HashSet<Constraint>, budget: &Budget) -> Option<Vec<GridPoint>> {
+    // 终点上最后一个约束之后才能停下
+    let min_arrival = constraints
+        .iter()
+        .filter_map(|c| match c {
+            Constraint::Vertex { cell, t } if *cell == agent.goal => Some(*t + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    plan_agent(grid, agent, min_arrival, |from, to, t| budget.spend() || is_constrained(constraints, from, to, t))
+}
+
+// 约束树节点
+struct CtNode {
+    cost: usize,
+    id: usize,
+    constraints: Vec<HashSet<Constraint>>,
+    paths: Vec<Vec<GridPoint>>,
+}
+
+impl Ord for CtNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.cmp(&self.cost).then(other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for CtNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CtNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost && self.id == other.id
+    }
+}
+
+impl Eq for CtNode {}
+
+// 第一个冲突, 返回两个机器人各自需要的约束
+fn first_conflict(paths: &[Vec<GridPoint>]) -> Option<[(usize, Constraint); 2]> {
+    let horizon = paths.iter().map(|path| path.len()).max().unwrap_or(0);
+    for t in 0..horizon.saturating_sub(1) {
+        for i in 0..paths.len() {
+            for j in (i + 1)..paths.len() {
+                let a = move_at(&paths[i], t);
+                let b = move_at(&paths[j], t);
+                if !moves_conflict(a, b) {
+                    continue;
+                }
+
+                let t = t as u32;
+                return Some(if chebyshev(a.1, b.1) < SEPARATION {
+                    [(i, Constraint::Vertex { cell: a.1, t: t + 1 }), (j, Constraint::Vertex { cell: b.1, t: t + 1 })]
+                } else {
+                    [(i, Constraint::Edge { from: a.0, to: a.1, t }), (j, Constraint::Edge { from: b.0, to: b.1, t })]
+                });
+            }
+        }
+    }
+
+    None
+}
+
+fn cost(paths: &[Vec<GridPoint>]) -> usize {
+    paths.iter().map(|path| path.len() - 1).sum()
+}
+
+// Conflict-Based Search, 超过 MAX_CBS_NODES 或 MAX_CBS_MOVES 时返回错误
+pub fn cbs(grid: &Grid, agents: &[MapfAgent]) -> Result<Vec<Vec<GridPoint>>, String> {
+    let budget = Budget::new();
+    let exhausted = || "conflict-based search exceeded its budget".to_string();
+
+    let constraints = vec![HashSet::new(); agents.len()];
+    let mut paths = Vec::with_capacity(agents.len());
+    for (agent, constraints) in agents.iter().zip(&constraints) {
+        let path = match plan_constrained(grid, agent, constraints, &budget) {
+            Some(path) => path,
+            None if budget.is_exhausted() => return Err(exhausted()),
+            None => return Err(format!("no path from ({}, {}) to ({}, {})", agent.start.gx, agent.start.gz, agent.goal.gx, agent.goal.gz)),
+        };
+        paths.push(path);
+    }
+
+    let mut open = BinaryHeap::new();
+    open.push(CtNode { cost: cost(&paths), id: 0, constraints, paths });
+
+    let mut next_id = 1;
+    while let Some(node) = open.pop() {
+        let conflict = match first_conflict(&node.paths) {
+            Some(conflict) => conflict,
+            None => return Ok(node.paths),
+        };
+
+        if next_id > MAX_CBS_NODES || budget.is_exhausted() {
+            return Err(exhausted());
+        }
+
+        for (agent, constraint) in conflict {
+            let mut constraints = node.constraints.clone();
+            constraints[agent].insert(constraint);
+
+            let Some(path) = plan_constrained(grid, &agents[agent], &constraints[agent], &budget) else {
+                continue;
+            };
+
+            let mut paths = node.paths.clone();
+            paths[agent] = path;
+            open.push(CtNode {
+                cost: cost(&paths),
+                id: next_id,
+                constraints,
+                paths,
+            });
+            next_id += 1;
+        }
+    }
+
+    Err("no conflict-free plan found".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 两个机器人对穿交换位置, 两种求解方式的路径都没有冲突, cbs 的总代价不大于 reservation
+    #[test]
+    fn swap_without_conflict() {
+        let grid = Grid::new(100, 100);
+        let agents = [
+            MapfAgent {
+                start: GridPoint { gx: 40, gz: 50 },
+                goal: GridPoint { gx: 60, gz: 50 },
+            },
+            MapfAgent {
+                start: GridPoint { gx: 60, gz: 50 },
+                goal: GridPoint { gx: 40, gz: 50 },
+            },
+        ];
+
+        let optimal = cbs(&grid, &agents).unwrap();
+        let ordered = solve(&grid, &agents, MapfSolver::Reservation).unwrap();
+        for paths in [&optimal, &ordered] {
+            assert!(first_conflict(paths).is_none());
+            for (path, agent) in paths.iter().zip(&agents) {
+                assert_eq!(path.first(), Some(&agent.start));
+                assert_eq!(path.last(), Some(&agent.goal));
+            }
+        }
+
+        assert!(cost(&optimal) <= cost(&ordered));
+        assert_eq!(cbs(&grid, &agents).unwrap(), optimal);
+    }
+}
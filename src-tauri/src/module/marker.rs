@@ -0,0 +1,26 @@
+/*!
+  标记点: 红旗、信标、出生点
+
+  标记点按名字唯一, 可以有任意多个, 所在格子会标记 `has_flag`, 防止障碍物生成在标记点上
+*/
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerKind {
+    Flag,
+    Beacon,
+    Spawn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Marker {
+    pub id: u32,
+    pub name: String,
+    pub kind: MarkerKind,
+    pub x: f32,
+    pub z: f32,
+    #[serde(default)]
+    pub metadata: serde_json::Value, // 前端自定义数据, 如颜色、图标
+}
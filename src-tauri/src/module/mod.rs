@@ -1,3 +1,25 @@
 pub mod a;
+pub mod animation;
+pub mod behaviour;
+pub mod coord;
+pub mod dynamic;
+pub mod fog;
 pub mod grid;
+pub mod hybrid;
+pub mod kinematics;
+pub mod mapf;
+pub mod marker;
+pub mod obstacle;
+pub mod orca;
+pub mod raycast;
 pub mod robot;
+pub mod robots;
+pub mod script;
+pub mod sensor;
+pub mod session;
+pub mod shape;
+pub mod simulation;
+pub mod storage;
+pub mod tracking;
+pub mod world;
+pub mod zone;
@@ -0,0 +1,119 @@
+/*!
+  障碍物类型注册表
+
+  障碍物类型不再写死在代码里, 而是从定义文件加载, 内置定义见 `resources/obstacles.json`:
+  ```
+   - id: 类型 id, 如 "pillar"、"rock"
+   - width/depth: 默认占用格子尺寸
+   - movement: block(阻挡) | slow(可通行, factor 为速度倍率)
+   - blocksSight: 是否遮挡视线
+   - render: 渲染用元数据, 原样透传给前端
+  ```
+  格子中只存类型在注册表中的序号(1 byte), 所以最多 255 种类型
+*/
+
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const BUILTIN_OBSTACLES: &str = include_str!("../../resources/obstacles.json");
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ObstacleMovement {
+    Block,
+    Slow { factor: f32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObstacleDef {
+    pub id: String,
+    pub width: usize,
+    pub depth: usize,
+    pub movement: ObstacleMovement,
+    #[serde(rename = "blocksSight", default)]
+    pub blocks_sight: bool,
+    #[serde(default)]
+    pub render: serde_json::Value,
+}
+
+impl ObstacleDef {
+    pub fn blocks_movement(&self) -> bool {
+        self.movement == ObstacleMovement::Block
+    }
+
+    // 通过该类型格子的代价倍率
+    pub fn move_cost(&self) -> f64 {
+        match self.movement {
+            ObstacleMovement::Block => f64::INFINITY,
+            ObstacleMovement::Slow { factor } => 1.0 / factor as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObstacleRegistry {
+    defs: Vec<ObstacleDef>,
+}
+
+impl ObstacleRegistry {
+    // 内置定义
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_OBSTACLES).expect("invalid builtin obstacles.json")
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Self::from_json(&content)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let defs: Vec<ObstacleDef> = serde_json::from_str(content).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Self::from_defs(defs)
+    }
+
+    pub fn from_defs(defs: Vec<ObstacleDef>) -> Result<Self, String> {
+        if defs.len() > u8::MAX as usize {
+            return Err(Error::convert_string("too many obstacle types, at most 255"));
+        }
+
+        for (i, def) in defs.iter().enumerate() {
+            if def.width == 0 || def.depth == 0 {
+                return Err(Error::convert_string(&format!("obstacle type `{}` has an empty footprint", def.id)));
+            }
+
+            if let ObstacleMovement::Slow { factor } = def.movement {
+                if !(factor.is_finite() && factor > 0.0 && factor <= 1.0) {
+                    return Err(Error::convert_string(&format!("obstacle type `{}` slow factor must be in (0, 1]", def.id)));
+                }
+            }
+
+            if defs[..i].iter().any(|d| d.id == def.id) {
+                return Err(Error::convert_string(&format!("duplicate obstacle type `{}`", def.id)));
+            }
+        }
+
+        Ok(Self { defs })
+    }
+
+    pub fn defs(&self) -> &[ObstacleDef] {
+        &self.defs
+    }
+
+    pub fn get(&self, id: &str) -> Result<&ObstacleDef, String> {
+        self.defs.iter().find(|d| d.id == id).ok_or_else(|| Error::convert_string(&format!("unknown obstacle type `{}`", id)))
+    }
+
+    // 格子存储编码, 0 表示无障碍物
+    pub fn code(&self, id: &str) -> Option<u8> {
+        self.defs.iter().position(|d| d.id == id).map(|i| (i + 1) as u8)
+    }
+
+    pub fn by_code(&self, code: u8) -> Option<&ObstacleDef> {
+        if code == 0 {
+            return None;
+        }
+
+        self.defs.get(code as usize - 1)
+    }
+}
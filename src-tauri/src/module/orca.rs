@@ -0,0 +1,281 @@
+/*!
+  局部避障: ORCA(Optimal Reciprocal Collision Avoidance)
+
+  每帧在机器人移动之前, 根据邻居调整每个机器人的速度:
+  ```
+   - 每个邻居对应一条半平面约束(ORCA 线), 速度必须落在所有半平面的交集内
+   - 两个都在移动的机器人各承担一半(responsibility = 0.5), 静止的机器人和障碍物格子由自己承担全部(1.0)
+   - 在满足约束的速度中选离期望速度(朝下一个路径点)最近的, 无解时选违反约束最少的(线性规划, 同 RVO2)
+  ```
+  障碍物格子按静止的圆处理, 半径略小于格子内切圆(不加机器人半径), 路径经过的线段不会进入阻挡格子, 所以不会和路径冲突
+*/
+
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Neg, Sub};
+
+const EPSILON: f32 = 1e-5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vec2 {
+    pub x: f32,
+    pub z: f32,
+}
+
+impl Vec2 {
+    pub fn new(x: f32, z: f32) -> Self {
+        Self { x, z }
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.z * other.z
+    }
+
+    // 二维叉积
+    pub fn det(self, other: Vec2) -> f32 {
+        self.x * other.z - self.z * other.x
+    }
+
+    pub fn length_sq(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_sq().sqrt()
+    }
+
+    pub fn normalize(self) -> Vec2 {
+        let length = self.length();
+        if length <= EPSILON {
+            Vec2::default()
+        } else {
+            self * (1.0 / length)
+        }
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.z + other.z)
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+    fn mul(self, value: f32) -> Vec2 {
+        Vec2::new(self.x * value, self.z * value)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+    fn neg(self) -> Vec2 {
+        Vec2::new(-self.x, -self.z)
+    }
+}
+
+// 邻居(机器人或障碍物格子)
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub radius: f32,         // 与自己的半径之和
+    pub responsibility: f32, // 自己承担的比例
+    pub time_horizon: f32,   // 预测多久之内不碰撞(秒)
+}
+
+// 半平面约束: 速度在 direction 左侧
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    point: Vec2,
+    direction: Vec2,
+}
+
+/**
+  计算新的速度
+  - position, velocity: 自己的位置、当前速度
+  - preferred: 期望速度
+  - max_speed: 最大速度
+  - delta: 帧时间, 已经重叠时用于尽快分开
+*/
+pub fn compute_velocity(position: Vec2, velocity: Vec2, preferred: Vec2, max_speed: f32, neighbors: &[Neighbor], delta: f32) -> Vec2 {
+    let lines: Vec<Line> = neighbors.iter().map(|neighbor| orca_line(position, velocity, neighbor, delta)).collect();
+
+    let mut result = Vec2::default();
+    let fail = linear_program2(&lines, max_speed, preferred, false, &mut result);
+    if fail < lines.len() {
+        linear_program3(&lines, fail, max_speed, &mut result);
+    }
+
+    result
+}
+
+// 单个邻居的 ORCA 线
+fn orca_line(position: Vec2, velocity: Vec2, neighbor: &Neighbor, delta: f32) -> Line {
+    let relative_position = neighbor.position - position;
+    let relative_velocity = velocity - neighbor.velocity;
+    let dist_sq = relative_position.length_sq();
+    let combined_radius = neighbor.radius;
+    let combined_radius_sq = combined_radius * combined_radius;
+
+    let direction;
+    let u;
+    if dist_sq > combined_radius_sq {
+        // 没有重叠
+        let inv_time_horizon = 1.0 / neighbor.time_horizon;
+        let w = relative_velocity - relative_position * inv_time_horizon;
+        let w_length_sq = w.length_sq();
+        let dot = w.dot(relative_position);
+
+        if dot < 0.0 && dot * dot > combined_radius_sq * w_length_sq {
+            // 投影到截断圆上
+            let w_length = w_length_sq.sqrt();
+            let unit_w = w * (1.0 / w_length);
+            direction = Vec2::new(unit_w.z, -unit_w.x);
+            u = unit_w * (combined_radius * inv_time_horizon - w_length);
+        } else {
+            // 投影到两条腿上
+            let leg = (dist_sq - combined_radius_sq).sqrt();
+            let (px, pz) = (relative_position.x, relative_position.z);
+            direction = if relative_position.det(w) > 0.0 {
+                Vec2::new(px * leg - pz * combined_radius, px * combined_radius + pz * leg) * (1.0 / dist_sq)
+            } else {
+                -(Vec2::new(px * leg + pz * combined_radius, -px * combined_radius + pz * leg) * (1.0 / dist_sq))
+            };
+            u = direction * relative_velocity.dot(direction) - relative_velocity;
+        }
+    } else {
+        // 已经重叠, 按帧时间尽快分开
+        let inv_time_step = 1.0 / delta.max(EPSILON);
+        let w = relative_velocity - relative_position * inv_time_step;
+        let w_length = w.length().max(EPSILON);
+        let unit_w = w * (1.0 / w_length);
+        direction = Vec2::new(unit_w.z, -unit_w.x);
+        u = unit_w * (combined_radius * inv_time_step - w_length);
+    }
+
+    Line {
+        point: velocity + u * neighbor.responsibility,
+        direction,
+    }
+}
+
+// 在第 line_no 条线上求解, 满足前面所有约束且在速度圆内
+fn linear_program1(lines: &[Line], line_no: usize, radius: f32, optimal: Vec2, direction_opt: bool, result: &mut Vec2) -> bool {
+    let line = lines[line_no];
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_sq();
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let mut t_left = -dot - sqrt_discriminant;
+    let mut t_right = -dot + sqrt_discriminant;
+
+    for other in &lines[..line_no] {
+        let denominator = line.direction.det(other.direction);
+        let numerator = other.direction.det(line.point - other.point);
+
+        if denominator.abs() <= EPSILON {
+            // 平行
+            if numerator < 0.0 {
+                return false;
+            }
+            continue;
+        }
+
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_right = t_right.min(t);
+        } else {
+            t_left = t_left.max(t);
+        }
+
+        if t_left > t_right {
+            return false;
+        }
+    }
+
+    *result = if direction_opt {
+        if optimal.dot(line.direction) > 0.0 {
+            line.point + line.direction * t_right
+        } else {
+            line.point + line.direction * t_left
+        }
+    } else {
+        let t = line.direction.dot(optimal - line.point);
+        line.point + line.direction * t.clamp(t_left, t_right)
+    };
+
+    true
+}
+
+// 满足所有约束且离 optimal 最近的速度, 返回第一条无法满足的线, 全部满足时返回 lines.len()
+fn linear_program2(lines: &[Line], radius: f32, optimal: Vec2, direction_opt: bool, result: &mut Vec2) -> usize {
+    *result = if direction_opt {
+        optimal * radius
+    } else if optimal.length_sq() > radius * radius {
+        optimal.normalize() * radius
+    } else {
+        optimal
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        if line.direction.det(line.point - *result) > 0.0 {
+            let previous = *result;
+            if !linear_program1(lines, i, radius, optimal, direction_opt, result) {
+                *result = previous;
+                return i;
+            }
+        }
+    }
+
+    lines.len()
+}
+
+// 无解时, 使违反约束的最大距离最小
+fn linear_program3(lines: &[Line], begin: usize, radius: f32, result: &mut Vec2) {
+    let mut distance = 0.0;
+
+    for i in begin..lines.len() {
+        if lines[i].direction.det(lines[i].point - *result) <= distance {
+            continue;
+        }
+
+        let mut projected = Vec::with_capacity(i);
+        for j in 0..i {
+            let determinant = lines[i].direction.det(lines[j].direction);
+            let point = if determinant.abs() <= EPSILON {
+                if lines[i].direction.dot(lines[j].direction) > 0.0 {
+                    // 同向平行
+                    continue;
+                }
+                (lines[i].point + lines[j].point) * 0.5
+            } else {
+                lines[i].point + lines[i].direction * (lines[j].direction.det(lines[i].point - lines[j].point) / determinant)
+            };
+
+            projected.push(Line {
+                point,
+                direction: (lines[j].direction - lines[i].direction).normalize(),
+            });
+        }
+
+        let previous = *result;
+        let optimal = Vec2::new(-lines[i].direction.z, lines[i].direction.x);
+        if linear_program2(&projected, radius, optimal, true, result) < projected.len() {
+            // 理论上不会发生, 浮点误差时保留上一次结果
+            *result = previous;
+        }
+
+        distance = lines[i].direction.det(lines[i].point - *result);
+    }
+}
@@ -0,0 +1,192 @@
+/*!
+  射线检测 / 视线检测
+
+  使用 DDA(Amanatides-Woo) 沿射线逐格遍历:
+  ```
+   - 从起点所在格子开始, 每次跨过离得最近的一条格子边线(X 或 Z)
+   - t 为沿射线走过的世界距离, 跨过 X 边线的 t 每次增加 tDeltaX, Z 同理
+   - 碰到阻挡格子时返回: 格子、障碍物、距离、命中点、法线(跨过的那条边线朝向射线来的方向)
+  ```
+  视线检测(`line_of_sight` / `line_clear`)最多检查 MAX_LINE_CELLS 个格子, 更远的两点视为不可见
+  `RayMask` 决定什么算阻挡: 视线按障碍物类型的 blocksSight, 移动按 blocked 和禁行区, 实体只按 blocked
+*/
+
+use crate::error::Error;
+use crate::module::grid::{Grid, GridPoint, Obstacle, ThreeGrid};
+use serde::{Deserialize, Serialize};
+
+// 外部传入(命令、脚本)的射线最多经过的格子数
+pub const MAX_RAY_CELLS: f32 = 100.0;
+
+// 两点之间检测的最长距离(格子数), 更远或坐标无效时视为不通
+pub const MAX_LINE_CELLS: f32 = 10_000.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RayMask {
+    #[default]
+    Sight, // 遮挡视线的格子
+    Movement, // 阻挡移动的格子
+    Solid,    // 实体障碍物(关闭的门、墙等, 不含禁行区), 用于传感器
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RayHit {
+    pub cell: GridPoint,
+    pub obstacle: Option<Obstacle>,
+    pub distance: f32,
+    pub point: ThreeGrid,
+    pub normal: ThreeGrid,
+}
+
+// 按 mask 判断格子是否阻挡射线
+pub fn is_ray_blocked(grid: &Grid, gx: i32, gz: i32, mask: RayMask) -> bool {
+    match mask {
+        RayMask::Movement => grid.is_blocked(gx, gz),
+        RayMask::Solid => grid.get_cell(gx, gz).blocked,
+        RayMask::Sight => {
+            let cell = grid.get_cell(gx, gz);
+            match grid.cell_def(&cell) {
+                // 打开的门(可阻挡类型但当前未阻挡)不遮挡视线
+                Some(def) => def.blocks_sight && (cell.blocked || !def.blocks_movement()),
+                None => cell.blocked,
+            }
+        }
+    }
+}
+
+// 限制外部传入的射线长度: 必须是有限的非负数, 超过 MAX_RAY_CELLS 个格子时截断
+pub fn clamp_ray_distance(grid: &Grid, max_distance: f32) -> Result<f32, String> {
+    if !(max_distance.is_finite() && max_distance >= 0.0) {
+        return Err(Error::convert_string("ray distance must be a finite non-negative number"));
+    }
+
+    Ok(max_distance.min(MAX_RAY_CELLS * grid.coords().cell_size))
+}
+
+// 射线检测, direction 不需要归一化
+pub fn raycast(grid: &Grid, origin: ThreeGrid, direction: ThreeGrid, max_distance: f32, mask: RayMask) -> Option<RayHit> {
+    let hit = traverse(grid, origin, direction, max_distance, |gx, gz| is_ray_blocked(grid, gx, gz, mask))?;
+
+    let obstacle = grid.get_cell(hit.cell.gx, hit.cell.gz).obstacle_id.and_then(|id| grid.get_obstacle(id)).cloned();
+    Some(RayHit { obstacle, ..hit })
+}
+
+// 两点之间是否可见
+pub fn line_of_sight(grid: &Grid, from: ThreeGrid, to: ThreeGrid, mask: RayMask) -> bool {
+    line_clear(grid, from, to, |gx, gz| is_ray_blocked(grid, gx, gz, mask))
+}
+
+// 两点之间经过的格子都不满足 blocked, 距离超过 MAX_LINE_CELLS 个格子时返回 false
+pub fn line_clear<F>(grid: &Grid, from: ThreeGrid, to: ThreeGrid, blocked: F) -> bool
+where
+    F: Fn(i32, i32) -> bool,
+{
+    let direction = ThreeGrid { x: to.x - from.x, z: to.z - from.z };
+    let distance = (direction.x * direction.x + direction.z * direction.z).sqrt();
+    if !distance.is_finite() || distance > MAX_LINE_CELLS * grid.coords().cell_size {
+        return false;
+    }
+
+    if distance == 0.0 {
+        let point = grid.point_to_cell(from.x, from.z);
+        return !blocked(point.gx, point.gz);
+    }
+
+    traverse(grid, from, direction, distance, blocked).is_none()
+}
+
+// DDA 遍历, 返回第一个 blocked 的格子(obstacle 为空)
+pub fn traverse<F>(grid: &Grid, origin: ThreeGrid, direction: ThreeGrid, max_distance: f32, mut blocked: F) -> Option<RayHit>
+where
+    F: FnMut(i32, i32) -> bool,
+{
+    // 非有限值无法遍历(t 不会前进)
+    let length = (direction.x * direction.x + direction.z * direction.z).sqrt();
+    if length == 0.0 || !(length.is_finite() && origin.x.is_finite() && origin.z.is_finite() && max_distance.is_finite()) {
+        return None;
+    }
+
+    let dir = ThreeGrid {
+        x: direction.x / length,
+        z: direction.z / length,
+    };
+
+    // 转到连续格子坐标, 沿射线每走 1 个世界单位, 格子坐标变化 (dcx, dcz)
+    let (ox, oz) = grid.coords().world_to_cell_f(origin.x, origin.z);
+    let (ex, ez) = grid.coords().world_to_cell_f(origin.x + dir.x, origin.z + dir.z);
+    let (dcx, dcz) = (ex - ox, ez - oz);
+
+    let mut cell = grid.point_to_cell(origin.x, origin.z);
+    let step_x = if dcx > 0.0 { 1 } else { -1 };
+    let step_z = if dcz > 0.0 { 1 } else { -1 };
+
+    let boundary = |c: i32, step: i32| if step > 0 { (c + 1) as f32 } else { c as f32 };
+    let mut t_max_x = if dcx != 0.0 { (boundary(cell.gx, step_x) - ox) / dcx } else { f32::INFINITY };
+    let mut t_max_z = if dcz != 0.0 { (boundary(cell.gz, step_z) - oz) / dcz } else { f32::INFINITY };
+    let t_delta_x = if dcx != 0.0 { 1.0 / dcx.abs() } else { f32::INFINITY };
+    let t_delta_z = if dcz != 0.0 { 1.0 / dcz.abs() } else { f32::INFINITY };
+
+    let hit = |cell: GridPoint, t: f32, normal: ThreeGrid| RayHit {
+        cell,
+        obstacle: None,
+        distance: t,
+        point: ThreeGrid {
+            x: origin.x + dir.x * t,
+            z: origin.z + dir.z * t,
+        },
+        normal,
+    };
+
+    let mut t = 0.0;
+    // 起点格子被挡住时, 法线取射线反方向
+    let mut normal = ThreeGrid { x: -dir.x, z: -dir.z };
+    let normal_x = ThreeGrid { x: -dir.x.signum(), z: 0.0 };
+    let normal_z = ThreeGrid { x: 0.0, z: -dir.z.signum() };
+
+    while t <= max_distance {
+        if blocked(cell.gx, cell.gz) {
+            return Some(hit(cell, t, normal));
+        }
+
+        // 正好穿过格子角点, 两侧格子都要检查, 防止从对角缝隙穿过
+        if (t_max_x - t_max_z).abs() < 1e-6 {
+            t = t_max_x;
+            if t > max_distance {
+                break;
+            }
+
+            let side_x = GridPoint { gx: cell.gx + step_x, gz: cell.gz };
+            if blocked(side_x.gx, side_x.gz) {
+                return Some(hit(side_x, t, normal_x));
+            }
+
+            let side_z = GridPoint { gx: cell.gx, gz: cell.gz + step_z };
+            if blocked(side_z.gx, side_z.gz) {
+                return Some(hit(side_z, t, normal_z));
+            }
+
+            t_max_x += t_delta_x;
+            t_max_z += t_delta_z;
+            cell.gx += step_x;
+            cell.gz += step_z;
+            normal = ThreeGrid { x: -dir.x, z: -dir.z };
+            continue;
+        }
+
+        // 跨过最近的一条边线, 法线指向射线来的方向(世界坐标)
+        if t_max_x < t_max_z {
+            t = t_max_x;
+            t_max_x += t_delta_x;
+            cell.gx += step_x;
+            normal = normal_x;
+        } else {
+            t = t_max_z;
+            t_max_z += t_delta_z;
+            cell.gz += step_z;
+            normal = normal_z;
+        }
+    }
+
+    None
+}
@@ -15,34 +15,43 @@
     ```
 */
 
-use crate::module::a::astar;
-use crate::module::grid::{Grid, GridPoint, GridResultPoint};
+use crate::module::a::{astar, smooth_path};
+use crate::module::animation::{Animation, AnimationTransition, RobotAction, RobotEmote};
+use crate::module::behaviour::{Behaviour, BehaviourStatus};
+use crate::module::fog::{BeliefCell, BeliefGrid};
+use crate::module::grid::{Grid, GridPoint, ThreeGrid};
+use crate::module::hybrid::hybrid_astar;
+use crate::module::kinematics::{wrap_angle, Kinematics, MotionModel, Pose};
+use crate::module::orca::Vec2;
+use crate::module::raycast::line_clear;
+use crate::module::sensor::{SensorConfig, SensorReading};
+use crate::module::tracking::{project, Tracking};
+use crate::module::zone::{Zone, ZoneEvent};
 use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-#[derive(Serialize, Clone, Copy, Debug, PartialEq, Deserialize)]
-pub enum RobotAction {
-    Idle,
-    Walking,
-    Running,
-    Dance,
-    Death,
-    Sitting,
-    Standing,
-}
+// 路径被挡住且无法重新规划时, 每隔多久重试一次(秒)
+const REPLAN_INTERVAL: f32 = 0.5;
 
-#[derive(Serialize, Clone, Copy, Debug, Deserialize)]
-pub enum RobotEmote {
-    None,
-    Jump,
-    Yes,
-    No,
-    Wave,
-    Punch,
-    ThumbsUp,
-}
+// 差速驱动: 朝向与移动方向的误差小于该值(弧度)才前进
+const ALIGN_TOLERANCE: f32 = 1e-3;
+
+// 类汽车: 路径点已在身后且距离小于该值时算作经过
+const WAYPOINT_TOLERANCE: f32 = 1.0;
+
+// 探索时每帧最多尝试规划几个边界, 其余留到下一帧
+const MAX_FRONTIER_ATTEMPTS: usize = 4;
 
-#[derive(Serialize, Clone, Copy, Debug, Deserialize)]
+// 带时间的路径: 晚于计划时间超过该值(秒)算作落后
+const SCHEDULE_TOLERANCE: f32 = 0.5;
+
+// 差速驱动跟踪路径时, 朝向误差小于该值(弧度)边转边走, 否则原地转向
+const TRACK_ALIGN_TOLERANCE: f32 = std::f32::consts::FRAC_PI_4;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Deserialize)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -51,19 +60,55 @@ pub struct Vec3 {
 
 #[derive(Serialize, Clone, Debug, Deserialize)]
 pub struct Robot {
+    id: u32,
     current: Vec3,
     target: Vec3,
     is_moving: bool,
     speed: f32,
+    base_speed: f32, // 生成时的速度, 没有固定速度的动作使用该速度
     rotation_y: f32,
-    action: RobotAction,
-    emote: RobotEmote,
+    animation: Animation,
+    behaviour: Option<Behaviour>, // 行为树, 每次 update 时执行
+    #[serde(skip)]
+    behaviour_result: Option<BehaviourStatus>, // 未推送给前端的行为树结果
+    #[serde(skip)]
+    target_request: Option<Vec3>, // 行为树请求的目标, 由 RobotRegistry 规划
     path: Vec<Vec3>,
     path_index: usize,
+    path_start: Vec3,   // 路径起点, 与路径点组成折线
+    goal: Option<Vec3>, // 最终目标, 路径被挡住时用于重新规划
+    waiting: bool,      // 路径被挡住(如门关闭), 原地等待
+    replan_timer: f32,
+    occupied: Vec<GridPoint>, // 当前占用的格子(2 * 2)
+    zones: Vec<u32>,          // 当前所在区域
+    sensors: Vec<SensorConfig>,
+    readings: Vec<SensorReading>, // 最近一次 update 的传感器读数
+    #[serde(skip)]
+    readings_changed: bool, // 读数变化后尚未推送
+    noise_seed: u64,              // 传感器噪声的随机种子, 每次读取后更新, 回放时结果一致
+    #[serde(skip)]
+    belief: Option<BeliefGrid>, // 迷雾模式下的认知地图, 为空时直接使用真实地图
+    #[serde(skip)]
+    belief_changes: Vec<BeliefCell>, // 未推送给前端的认知变化
+    exploring: bool,              // 自动探索边界
+    #[serde(skip)]
+    unreachable: HashSet<GridPoint>, // 无法到达的边界
+    velocity: Vec2,               // 上一帧的速度
+    #[serde(skip)]
+    avoidance: Option<Vec2>, // 本帧避障后的速度, 由 RobotRegistry 设置
+    schedule: Vec<f32>,           // 带时间的路径: 每个路径点最早到达的时间(秒), 为空时尽快到达
+    clock: f32,                   // 带时间的路径开始后经过的时间
+    kinematics: Kinematics,
+    motion: MotionModel,
+    tracking: Tracking,
+    cross_track_error: f32, // 偏离路径的距离, 见 `tracking`
+    linear_speed: f32,      // 当前速度
+    angular_velocity: f32,  // 当前角速度, 逆时针为正
 }
 
-#[derive(Serialize, Clone, Copy, Debug, Deserialize)]
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Deserialize)]
 pub struct RobotState {
+    pub id: u32,
     pub position: Vec3,
     #[serde(rename = "isMoving")]
     pub is_moving: bool,
@@ -71,35 +116,96 @@ pub struct RobotState {
     pub rotation_y: f32, // 朝向
     #[serde(rename = "pathIndex")]
     pub path_index: usize, // 路径
+    pub speed: f32, // 当前速度
+    #[serde(rename = "angularVelocity")]
+    pub angular_velocity: f32,
+    #[serde(rename = "crossTrackError")]
+    pub cross_track_error: f32,
+    pub action: RobotAction,
+    pub emote: RobotEmote,
 }
 
 impl Robot {
-    pub fn new(start_x: f32, start_z: f32, speed: f32) -> Self {
-        info!("Robot created!");
+    pub fn new(id: u32, start_x: f32, start_z: f32, speed: f32) -> Self {
+        info!("Robot {} created!", id);
         Self {
+            id,
             current: Vec3 { x: start_x, y: 0f32, z: start_z },
             target: Vec3 { x: start_x, y: 0f32, z: start_z },
             is_moving: false,
             rotation_y: 0f32,
-            action: RobotAction::Idle,
-            emote: RobotEmote::None,
+            animation: Animation::default(),
+            behaviour: None,
+            behaviour_result: None,
+            target_request: None,
             path: vec![],
             speed,
+            base_speed: speed,
             path_index: 0,
+            path_start: Vec3 { x: start_x, y: 0f32, z: start_z },
+            goal: None,
+            waiting: false,
+            replan_timer: 0.0,
+            occupied: Vec::new(),
+            zones: Vec::new(),
+            sensors: SensorConfig::defaults(),
+            readings: Vec::new(),
+            readings_changed: false,
+            noise_seed: id as u64,
+            belief: None,
+            belief_changes: Vec::new(),
+            exploring: false,
+            unreachable: HashSet::new(),
+            velocity: Vec2::default(),
+            avoidance: None,
+            schedule: Vec::new(),
+            clock: 0.0,
+            kinematics: Kinematics::default(),
+            motion: MotionModel::default(),
+            tracking: Tracking::default(),
+            cross_track_error: 0.0,
+            linear_speed: 0.0,
+            angular_velocity: 0.0,
         }
     }
 
     // 设置目标格子
-    pub fn set_target(&mut self, grid: &Grid, x: f32, z: f32) -> Vec<Vec3> {
+    pub fn set_target(&mut self, grid: &mut Grid, x: f32, z: f32) -> Vec<Vec3> {
         // 如果正在移动，先对齐到当前目标格
         if self.is_moving {
             info!("机器人正在移动, 重新设置终点 ...");
         }
 
-        let start = self.current;
         let goal = Vec3 { x, y: 0.0, z };
+        self.waiting = false;
+        if self.plan(grid, goal) {
+            self.goal = Some(goal);
+        } else {
+            info!("A* 无法到达目标");
+            self.goal = None;
+        }
+
+        // self.target = Vec3 { x, z, y: 0f32 };
+        // self.is_moving = true;
+
+        self.path.clone()
+    }
 
-        if let Some(mut path) = astar(grid, start, goal) {
+    // 规划到 goal 的路径, 失败时保持原地
+    fn plan(&mut self, grid: &mut Grid, goal: Vec3) -> bool {
+        let path = match &self.belief {
+            // 迷雾模式在认知地图上规划, 未知格子按空闲处理
+            Some(belief) => self.find_path(belief.map(), goal),
+            None => {
+                // 规划时先释放自己占用的格子, 否则会挡住自己
+                grid.set_occupied(self.id, &self.occupied, false);
+                let path = self.find_path(grid, goal);
+                grid.set_occupied(self.id, &self.occupied, true);
+                path
+            }
+        };
+
+        if let Some(mut path) = path {
             if !path.is_empty() {
                 path.remove(0);
             }
@@ -107,6 +213,8 @@ impl Robot {
             self.path = path.into_iter().map(|p| Vec3 { x: p.x, y: 0.0, z: p.z }).collect();
 
             self.path_index = 0;
+            self.path_start = self.current;
+            self.schedule.clear();
 
             if !self.path.is_empty() {
                 self.target = self.path[0];
@@ -114,22 +222,96 @@ impl Robot {
             } else {
                 self.is_moving = false;
             }
-        } else {
-            info!("A* 无法到达目标");
-            self.is_moving = false;
+
+            return true;
         }
 
-        // self.target = Vec3 { x, z, y: 0f32 };
-        // self.is_moving = true;
+        self.path.clear();
+        self.path_index = 0;
+        self.schedule.clear();
+        self.is_moving = false;
+        false
+    }
 
-        self.path.clone()
+    // 按运动模型规划: 类汽车用 Hybrid A*, 其它用 A* + 平滑
+    fn find_path(&self, grid: &Grid, goal: Vec3) -> Option<Vec<ThreeGrid>> {
+        match self.motion {
+            MotionModel::Ackermann { min_turn_radius } => {
+                let start = Pose {
+                    x: self.current.x,
+                    z: self.current.z,
+                    yaw: self.rotation_y,
+                };
+                hybrid_astar(grid, start, ThreeGrid { x: goal.x, z: goal.z }, min_turn_radius)
+            }
+            _ => astar(grid, self.current, goal).map(|path| smooth_path(grid, path)),
+        }
+    }
+
+    // 设置带时间的路径(多机器人规划的结果), times[i] 为到达 points[i] 的时间(秒), 第一个点为起点
+    pub fn set_timed_path(&mut self, points: Vec<Vec3>, times: Vec<f32>) {
+        self.waiting = false;
+        self.path_start = points.first().copied().unwrap_or(self.current);
+        self.path = points.into_iter().skip(1).collect();
+        self.schedule = times.into_iter().skip(1).collect();
+        self.path_index = 0;
+        self.clock = 0.0;
+        self.goal = self.path.last().copied();
+        self.is_moving = !self.path.is_empty();
+        if let Some(target) = self.path.first() {
+            self.target = *target;
+        }
+    }
+
+    // 剩余路径上是否有格子被挡住(门关闭、新障碍物等), 平滑后的路径按线段检查
+    fn is_path_blocked(&self, grid: &Grid) -> bool {
+        let grid = self.belief.as_ref().map(|belief| belief.map()).unwrap_or(grid);
+        let mut from = ThreeGrid { x: self.current.x, z: self.current.z };
+        for p in self.path.iter().skip(self.path_index) {
+            let to = ThreeGrid { x: p.x, z: p.z };
+            if !line_clear(grid, from, to, |gx, gz| grid.is_blocked(gx, gz)) {
+                return true;
+            }
+
+            from = to;
+        }
+
+        false
+    }
+
+    // 路径被挡住时重新规划, 规划失败则原地等待, 定时重试
+    fn replan(&mut self, grid: &mut Grid, delta: f32) -> bool {
+        let goal = match self.goal {
+            Some(goal) => goal,
+            None => return false,
+        };
+
+        if self.waiting {
+            self.replan_timer -= delta;
+            if self.replan_timer > 0.0 {
+                return false;
+            }
+        }
+
+        info!("路径被挡住, 重新规划 ...");
+        if self.plan(grid, goal) {
+            self.waiting = false;
+            return true;
+        }
+
+        self.waiting = true;
+        self.replan_timer = REPLAN_INTERVAL;
+        false
     }
 
     // 清除路径
     pub fn clear_path(&mut self) {
         self.path.clear();
+        self.schedule.clear();
         self.path_index = 0;
         self.is_moving = false;
+        self.goal = None;
+        self.waiting = false;
 
         // 让目标回到当前位置
         // self.target = self.current;
@@ -144,7 +326,410 @@ impl Robot {
 
      ⚠ 注意顺序是 atan2(x, z) 还是 atan2(z, x) 取决于坐标系
     */
-    pub fn update(&mut self, delta: f32) {
+    pub fn update(&mut self, grid: &mut Grid, delta: f32) -> Vec<ZoneEvent> {
+        let rotation_y = self.rotation_y;
+        self.animation.update(delta);
+        let locked = self.animation.locks_movement();
+        if locked {
+            self.velocity = Vec2::default();
+            self.avoidance = None;
+        } else {
+            self.step(grid, delta);
+        }
+
+        self.linear_speed = self.velocity.length();
+        self.cross_track_error = if self.is_moving {
+            project(&self.polyline(), self.path_index, Vec2::new(self.current.x, self.current.z)).error
+        } else {
+            0.0
+        };
+        self.angular_velocity = if delta > 0.0 { wrap_angle(self.rotation_y - rotation_y) / delta } else { 0.0 };
+        // 动作锁定移动时带时间的路径暂停计时, 解锁后从原地按剩余计划继续
+        if !self.schedule.is_empty() && !locked {
+            self.clock += delta;
+        }
+
+        self.sync_occupancy(grid);
+        self.sense(grid);
+        self.update_belief(grid);
+        self.explore(grid);
+        self.sync_zones(grid)
+    }
+
+    // 执行一次行为树, 结束时记录结果, 由 `RobotRegistry` 在 update 之前调用
+    pub fn run_behaviour(&mut self, grid: &mut Grid, delta: f32) {
+        let Some(mut behaviour) = self.behaviour.take() else {
+            return;
+        };
+
+        if behaviour.status == BehaviourStatus::Running {
+            let status = behaviour.tick(self, grid, delta);
+            if status != BehaviourStatus::Running {
+                info!("Robot {} behaviour finished: {:?}", self.id, status);
+                self.behaviour_result = Some(status);
+            }
+        }
+
+        self.behaviour = Some(behaviour);
+    }
+
+    // 请求前往 (x, z), 需要避开其它机器人, 由 `RobotRegistry` 规划
+    pub fn request_target(&mut self, x: f32, z: f32) {
+        self.target_request = Some(Vec3 { x, y: 0.0, z });
+    }
+
+    pub fn take_target_request(&mut self) -> Option<Vec3> {
+        self.target_request.take()
+    }
+
+    // 设置行为树, 为空时停止
+    pub fn set_behaviour(&mut self, behaviour: Option<Behaviour>) {
+        self.behaviour = behaviour;
+        self.behaviour_result = None;
+    }
+
+    pub fn get_behaviour(&self) -> Option<&Behaviour> {
+        self.behaviour.as_ref()
+    }
+
+    // 写入行为树的黑板, 用于前端触发反应
+    pub fn set_blackboard(&mut self, key: String, value: serde_json::Value) -> Result<(), String> {
+        let behaviour = self.behaviour.as_mut().ok_or_else(|| format!("robot `{}` has no behaviour", self.id))?;
+        behaviour.blackboard.insert(key, value);
+        Ok(())
+    }
+
+    // 取出未推送的行为树结果
+    pub fn take_behaviour_result(&mut self) -> Option<BehaviourStatus> {
+        self.behaviour_result.take()
+    }
+
+    // 迷雾模式: 用传感器更新认知地图, 发现新的阻挡后剩余路径被挡住, 下一帧会重新规划
+    fn update_belief(&mut self, grid: &Grid) {
+        if let Some(belief) = &mut self.belief {
+            let changes = belief.sense(grid, self.current.x, self.current.z, self.rotation_y, &self.sensors);
+            self.belief_changes.extend(changes);
+        }
+    }
+
+    // 探索: 空闲时前往最近的边界, 没有边界时结束探索, 每帧最多尝试 MAX_FRONTIER_ATTEMPTS 个边界
+    fn explore(&mut self, grid: &mut Grid) {
+        if !self.exploring || (self.is_moving && !self.waiting) {
+            return;
+        }
+
+        // 前往的边界被挡住无法到达, 换一个
+        if self.waiting {
+            if let Some(goal) = self.goal {
+                self.unreachable.insert(grid.point_to_cell(goal.x, goal.z));
+            }
+            self.clear_path();
+        }
+
+        let start = grid.point_to_cell(self.current.x, self.current.z);
+        for _ in 0..MAX_FRONTIER_ATTEMPTS {
+            let frontier = match self.belief.as_ref().and_then(|belief| belief.nearest_frontier(start, &self.unreachable)) {
+                Some(frontier) => frontier,
+                None => {
+                    info!("探索结束");
+                    self.exploring = false;
+                    return;
+                }
+            };
+
+            let center = grid.cell_center(frontier.gx, frontier.gz);
+            let goal = Vec3 { x: center.x, y: 0.0, z: center.z };
+            if self.plan(grid, goal) && self.is_moving {
+                self.goal = Some(goal);
+                return;
+            }
+
+            self.unreachable.insert(frontier);
+        }
+    }
+
+    // 开启|关闭迷雾模式, 开启时认知地图清空并立即观察一次
+    pub fn set_fog(&mut self, grid: &Grid, enabled: bool) {
+        self.belief_changes.clear();
+        self.unreachable.clear();
+        if !enabled {
+            self.belief = None;
+            self.exploring = false;
+            return;
+        }
+
+        self.belief = Some(BeliefGrid::new(grid));
+        self.update_belief(grid);
+    }
+
+    // 开启|关闭自动探索, 需要先开启迷雾模式
+    pub fn set_exploring(&mut self, enabled: bool) -> Result<(), String> {
+        if enabled && self.belief.is_none() {
+            return Err("exploration requires fog of war".to_string());
+        }
+
+        self.exploring = enabled;
+        self.unreachable.clear();
+        if !enabled {
+            self.clear_path();
+        }
+
+        Ok(())
+    }
+
+    pub fn is_exploring(&self) -> bool {
+        self.exploring
+    }
+
+    pub fn get_belief(&self) -> Option<&BeliefGrid> {
+        self.belief.as_ref()
+    }
+
+    // 取出未推送的认知变化
+    pub fn take_belief_changes(&mut self) -> Vec<BeliefCell> {
+        std::mem::take(&mut self.belief_changes)
+    }
+
+    // 读取所有传感器, 读数有变化时标记为待推送
+    fn sense(&mut self, grid: &Grid) {
+        let mut rng = StdRng::seed_from_u64(self.noise_seed);
+        let readings: Vec<SensorReading> = self.sensors.iter().map(|sensor| sensor.read(grid, self.current.x, self.current.z, self.rotation_y, &mut rng)).collect();
+        self.noise_seed = rng.random();
+
+        if readings != self.readings {
+            self.readings = readings;
+            self.readings_changed = true;
+        }
+    }
+
+    // 设置传感器, 名字不能重复, 参数见 `SensorConfig::validate`
+    pub fn set_sensors(&mut self, grid: &Grid, sensors: Vec<SensorConfig>) -> Result<(), String> {
+        for (i, sensor) in sensors.iter().enumerate() {
+            sensor.validate()?;
+            if sensors[..i].iter().any(|other| other.name() == sensor.name()) {
+                return Err(format!("duplicate sensor name `{}`", sensor.name()));
+            }
+        }
+
+        self.sensors = sensors;
+        self.sense(grid);
+        Ok(())
+    }
+
+    pub fn get_sensors(&self) -> &[SensorConfig] {
+        &self.sensors
+    }
+
+    pub fn get_readings(&self) -> &[SensorReading] {
+        &self.readings
+    }
+
+    // 读数是否在上次推送后发生变化
+    pub fn take_readings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.readings_changed)
+    }
+
+    // 根据当前所在格子更新所在区域(与规划一致, 按格子中心判断), 返回进入|离开事件
+    fn sync_zones(&mut self, grid: &Grid) -> Vec<ZoneEvent> {
+        let point = grid.point_to_cell(self.current.x, self.current.z);
+        let center = grid.cell_center(point.gx, point.gz);
+        let zones: Vec<&Zone> = grid.zones_at(center.x, center.z).collect();
+
+        let mut events = Vec::new();
+        for zone in &zones {
+            if !self.zones.contains(&zone.id) {
+                events.push(ZoneEvent {
+                    robot_id: self.id,
+                    zone_id: zone.id,
+                    name: zone.name.clone(),
+                    entered: true,
+                });
+            }
+        }
+
+        for id in &self.zones {
+            if !zones.iter().any(|zone| zone.id == *id) {
+                let name = grid.zones().iter().find(|zone| zone.id == *id).map(|zone| zone.name.clone()).unwrap_or_default();
+                events.push(ZoneEvent {
+                    robot_id: self.id,
+                    zone_id: *id,
+                    name,
+                    entered: false,
+                });
+            }
+        }
+
+        self.zones = zones.iter().map(|zone| zone.id).collect();
+        events
+    }
+
+    // 根据当前位置更新占用的格子
+    pub fn sync_occupancy(&mut self, grid: &mut Grid) {
+        let cells = grid.robot_cells(self.current.x, self.current.z);
+        if cells == self.occupied {
+            return;
+        }
+
+        grid.set_occupied(self.id, &self.occupied, false);
+        grid.set_occupied(self.id, &cells, true);
+        self.occupied = cells;
+    }
+
+    // 释放占用的格子
+    pub fn release_occupancy(&mut self, grid: &mut Grid) {
+        grid.set_occupied(self.id, &self.occupied, false);
+        self.occupied.clear();
+    }
+
+    /**
+      按剩余路径和速度预测之后每个时间步所在的格子, 下标为时间步, 最后一个为终点
+      带时间的路径不早于计划时间到达路径点
+    */
+    pub fn predict_cells(&self, grid: &Grid, step: f32) -> Vec<GridPoint> {
+        let speed = self.max_speed(grid);
+        let mut frames = vec![(0.0, self.current)];
+        if self.is_moving && speed > 0.0 {
+            for (i, point) in self.path.iter().enumerate().skip(self.path_index) {
+                let (time, last) = frames[frames.len() - 1];
+                let distance = ((point.x - last.x).powi(2) + (point.z - last.z).powi(2)).sqrt();
+                let eta = self.schedule.get(i).map(|eta| eta - self.clock).unwrap_or(0.0);
+                frames.push(((time + distance / speed).max(eta), *point));
+            }
+        }
+
+        let end = frames[frames.len() - 1].0;
+        let mut cells = Vec::new();
+        let mut k = 0;
+        let mut frame = 0;
+        loop {
+            let t = k as f32 * step;
+            while frame + 1 < frames.len() && frames[frame + 1].0 <= t {
+                frame += 1;
+            }
+
+            let (t0, from) = frames[frame];
+            let point = match frames.get(frame + 1) {
+                Some((t1, to)) => {
+                    let ratio = if *t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                    Vec3 {
+                        x: from.x + (to.x - from.x) * ratio,
+                        y: 0.0,
+                        z: from.z + (to.z - from.z) * ratio,
+                    }
+                }
+                None => from,
+            };
+
+            cells.push(grid.point_to_cell(point.x, point.z));
+            if t >= end {
+                return cells;
+            }
+
+            k += 1;
+        }
+    }
+
+    // 是否正在按带时间的路径移动
+    pub fn is_timed(&self) -> bool {
+        self.is_moving && !self.schedule.is_empty()
+    }
+
+    // 带时间的路径: 已经晚于当前路径点的计划时间超过 SCHEDULE_TOLERANCE, 被挡住时需要重新参与避让
+    pub fn is_behind_schedule(&self) -> bool {
+        self.is_timed() && self.schedule.get(self.path_index).is_some_and(|eta| self.clock - eta > SCHEDULE_TOLERANCE)
+    }
+
+    // 不考虑减速区的基础速度
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
+    // 本帧的最大速度, 减速区内按倍率减速
+    pub fn max_speed(&self, grid: &Grid) -> f32 {
+        self.speed * grid.speed_factor(self.current.x, self.current.z)
+    }
+
+    // 带时间的路径: 本帧结束前是否到了当前路径点的计划时间
+    fn is_due(&self, delta: f32) -> bool {
+        self.schedule.get(self.path_index).is_none_or(|eta| eta - self.clock <= delta)
+    }
+
+    // 到最终路径点的剩余距离, 差速驱动到下一个拐点为止(拐点需要停下转向)
+    fn remaining_distance(&self) -> f32 {
+        let mut from = self.current;
+        let mut heading = None;
+        let mut distance = 0.0;
+        for p in self.path.iter().skip(self.path_index) {
+            let (dx, dz) = (p.x - from.x, p.z - from.z);
+            let length = (dx * dx + dz * dz).sqrt();
+            if length <= f32::EPSILON {
+                continue;
+            }
+
+            let yaw = dx.atan2(dz);
+            if self.motion == MotionModel::Differential && heading.is_some_and(|heading: f32| wrap_angle(yaw - heading).abs() > ALIGN_TOLERANCE) {
+                break;
+            }
+
+            distance += length;
+            heading = Some(yaw);
+            from = *p;
+        }
+
+        distance
+    }
+
+    // 本帧最大可移动距离, 按加速度、刹车距离限制, 带时间的路径不会早于计划时间到达路径点
+    fn max_step(&self, grid: &Grid, delta: f32) -> f32 {
+        // 类汽车转弯时角速度为 v / r, 不能超过最大角速度
+        let max_speed = match self.motion {
+            MotionModel::Ackermann { min_turn_radius } => self.max_speed(grid).min(self.kinematics.max_angular_speed * min_turn_radius),
+            _ => self.max_speed(grid),
+        };
+        let max_step = self.kinematics.next_speed(self.linear_speed, max_speed, self.remaining_distance(), delta) * delta;
+        if self.is_due(delta) {
+            return max_step;
+        }
+
+        let remaining = self.schedule[self.path_index] - self.clock;
+
+        let distance = ((self.target.x - self.current.x).powi(2) + (self.target.z - self.current.z).powi(2)).sqrt();
+        max_step.min(distance * delta / remaining)
+    }
+
+    // 期望速度: 朝当前路径点, 本帧不会越过路径点
+    pub fn preferred_velocity(&self, grid: &Grid, delta: f32) -> Vec2 {
+        if !self.is_moving || self.waiting || delta <= 0.0 || self.animation.locks_movement() {
+            return Vec2::default();
+        }
+
+        let offset = Vec2::new(self.target.x - self.current.x, self.target.z - self.current.z);
+        let max_step = self.max_step(grid, delta);
+        if offset.length() <= max_step && self.is_due(delta) {
+            offset * (1.0 / delta)
+        } else {
+            offset.normalize() * (max_step / delta)
+        }
+    }
+
+    // 设置本帧避障后的速度, 下一次 update 时生效
+    pub fn set_avoidance(&mut self, velocity: Vec2) {
+        self.avoidance = Some(velocity);
+    }
+
+    pub fn get_velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    fn step(&mut self, grid: &mut Grid, delta: f32) {
+        let avoidance = self.avoidance.take();
+        self.velocity = Vec2::default();
+
+        // 路径被挡住时重新规划, 规划失败原地等待, 不会走进关闭的门
+        if (self.waiting || (self.is_moving && self.is_path_blocked(grid))) && !self.replan(grid, delta) {
+            return;
+        }
+
         if !self.is_moving {
             return;
         }
@@ -155,88 +740,300 @@ impl Robot {
         // 计算向量长度, 公式: √(x² + y² + z²)
         let distance = (dx * dx + dz * dz).sqrt();
 
-        // 本帧最大可移动距离
-        let max_step = self.speed * delta;
-        if distance <= max_step {
+        // 本帧最大可移动距离, 减速区内按倍率减速
+        let max_step = self.max_step(grid, delta);
+
+        // 被避障调整过的速度直接使用; 占用的格子会碰到其它机器人时本帧原地等待, 碰到阻挡时忽略避障速度, 按路径移动
+        let preferred = self.preferred_velocity(grid, delta);
+        if let Some(velocity) = avoidance.filter(|velocity| (*velocity - preferred).length_sq() > 1e-6) {
+            let next = Vec2::new(self.current.x, self.current.z) + velocity * delta;
+            if self.is_footprint_free(grid, next.x, next.z) {
+                if velocity.length_sq() > 1e-6 {
+                    self.rotation_y = self.kinematics.turn(self.rotation_y, velocity.x.atan2(velocity.z), delta);
+                }
+
+                self.current.x = next.x;
+                self.current.z = next.z;
+                self.velocity = velocity;
+
+                // 绕行时越过或接近中间路径点也算经过, 不再绕回去
+                let offset = Vec2::new(self.target.x - next.x, self.target.z - next.z);
+                let passed = offset.x * dx + offset.z * dz <= 0.0;
+                if self.path_index + 1 < self.path.len() && (passed || offset.length() <= max_step) && self.is_due(delta) {
+                    self.path_index += 1;
+                    self.target = self.path[self.path_index];
+                }
+                return;
+            }
+
+            if self.is_footprint_crowded(grid, next.x, next.z) {
+                return;
+            }
+        }
+
+        if self.track(grid, max_step, delta) {
+            return;
+        }
+
+        // 类汽车无法精确经过路径点, 路径点已在身后且足够近时也算经过
+        let (sin, cos) = self.rotation_y.sin_cos();
+        let passed = matches!(self.motion, MotionModel::Ackermann { .. }) && dx * sin + dz * cos < 0.0 && distance <= WAYPOINT_TOLERANCE;
+
+        self.velocity = preferred;
+        if (distance <= max_step || passed) && self.is_due(delta) {
             info!("update distance: {}, max_step: {}", distance, max_step);
             // self.current = self.target;
             // self.is_moving = false;
-            self.current = self.target;
+            if distance <= max_step {
+                self.current = self.target;
+            }
 
             self.path_index += 1;
 
             if self.path_index >= self.path.len() {
                 self.is_moving = false;
+                self.goal = None;
+                self.velocity = Vec2::default();
                 return;
             }
 
+            // 经过中间路径点不减速
+            self.velocity = Vec2::new(dx, dz).normalize() * (max_step / delta);
+
             self.target = self.path[self.path_index];
             info!("到达目标，停止移动 ...");
             return;
         }
 
+        // 带时间的路径: 已在路径点上, 等到计划时间再继续
+        if distance <= f32::EPSILON {
+            return;
+        }
+
         let dir_x = dx / distance;
         let dir_z = dz / distance;
 
-        let yaw = dir_x.atan2(dir_z);
-        self.rotation_y = yaw;
-
-        self.current.x += dir_x * max_step;
-        self.current.z += dir_z * max_step;
+        self.drive(Vec2::new(dir_x, dir_z), ALIGN_TOLERANCE, max_step, delta);
 
         println!("current: {:?}", self.current);
     }
 
-    // 设置动作
-    pub fn set_action(&mut self, action: String) {
-        self.action = match action.as_str() {
-            "walking" => RobotAction::Walking,
-            "running" => RobotAction::Running,
-            "dance" => RobotAction::Dance,
-            "death" => RobotAction::Death,
-            "idle" => RobotAction::Idle,
-            "sitting" => RobotAction::Sitting,
-            "standing" => RobotAction::Standing,
-            _ => RobotAction::Idle,
+    // 路径折线: 起点 + 路径点
+    fn polyline(&self) -> Vec<Vec2> {
+        std::iter::once(&self.path_start).chain(&self.path).map(|p| Vec2::new(p.x, p.z)).collect()
+    }
+
+    /**
+      路径跟踪控制器: 按整条剩余路径计算移动朝向, 投影越过路径点即切换到下一个
+      direct、带时间的路径、最后一段路径(到达终点)或本帧实际移动后会碰到阻挡、其它机器人时返回 false, 按路径点直接移动
+    */
+    fn track(&mut self, grid: &Grid, max_step: f32, delta: f32) -> bool {
+        if self.tracking == Tracking::Direct || !self.schedule.is_empty() {
+            return false;
+        }
+
+        let points = self.polyline();
+        let position = Vec2::new(self.current.x, self.current.z);
+        let projection = project(&points, self.path_index, position);
+        self.path_index = projection.segment;
+        self.target = self.path[self.path_index];
+        if self.path_index + 1 >= self.path.len() {
+            return false;
+        }
+
+        let direction = match self.tracking.direction(&points, &projection, position, self.linear_speed) {
+            Some(direction) if direction.length_sq() > 0.0 => direction,
+            _ => return false,
         };
 
-        // running
-        if self.action == RobotAction::Running {
-            self.set_speed(5.0)
+        // 按运动模型实际的移动方向检查, 差速驱动、类汽车不一定沿 direction 移动
+        let (yaw, heading) = self.steer(direction, TRACK_ALIGN_TOLERANCE, max_step, delta);
+        if let Some(heading) = heading {
+            let next = position + heading * max_step;
+            if !self.is_footprint_free(grid, next.x, next.z) {
+                return false;
+            }
         }
 
-        // walking
-        if self.action == RobotAction::Walking {
-            self.set_speed(2.5)
+        self.apply_drive(yaw, heading, max_step, delta);
+        true
+    }
+
+    // 机器人在 (x, z) 时占用的格子都没有阻挡, 也没有被其它机器人占用
+    fn is_footprint_free(&self, grid: &Grid, x: f32, z: f32) -> bool {
+        grid.robot_cells(x, z).iter().all(|cell| !grid.is_blocked(cell.gx, cell.gz) && grid.occupants(cell.gx, cell.gz).iter().all(|&owner| owner == self.id))
+    }
+
+    // 机器人在 (x, z) 时占用的格子是否有被其它机器人占用的
+    fn is_footprint_crowded(&self, grid: &Grid, x: f32, z: f32) -> bool {
+        grid.robot_cells(x, z).iter().any(|cell| grid.occupants(cell.gx, cell.gz).iter().any(|&owner| owner != self.id))
+    }
+
+    /**
+      按运动模型朝 direction(单位向量)移动时, 本帧结束后的朝向和实际移动方向(单位向量)
+      差速驱动朝向误差大于 align_tolerance 时只原地转向, 移动方向为 None
+    */
+    fn steer(&self, direction: Vec2, align_tolerance: f32, max_step: f32, delta: f32) -> (f32, Option<Vec2>) {
+        let yaw = direction.x.atan2(direction.z);
+        match self.motion {
+            MotionModel::Holonomic => (self.kinematics.turn(self.rotation_y, yaw, delta), Some(direction)),
+            // 差速驱动: 先原地转向, 对准后再前进
+            MotionModel::Differential => {
+                let rotation_y = self.kinematics.turn(self.rotation_y, yaw, delta);
+                if wrap_angle(yaw - rotation_y).abs() > align_tolerance {
+                    return (rotation_y, None);
+                }
+
+                (rotation_y, Some(Vec2::new(rotation_y.sin(), rotation_y.cos())))
+            }
+            // 类汽车: 转向受转弯半径限制, 沿朝向前进
+            MotionModel::Ackermann { min_turn_radius } => {
+                let max_turn = max_step / min_turn_radius;
+                let turn = wrap_angle(yaw - self.rotation_y).clamp(-max_turn, max_turn);
+                let rotation_y = self.kinematics.turn(self.rotation_y, self.rotation_y + turn, delta);
+                (rotation_y, Some(Vec2::new(rotation_y.sin(), rotation_y.cos())))
+            }
         }
     }
 
-    // 设置表情
-    pub fn set_emote(&mut self, emote: String) {
-        self.emote = match emote.as_str() {
-            "jump" => RobotEmote::Jump,
-            "yes" => RobotEmote::Yes,
-            "no" => RobotEmote::No,
-            "wave" => RobotEmote::Wave,
-            "punch" => RobotEmote::Punch,
-            "thumbsup" => RobotEmote::ThumbsUp,
-            _ => RobotEmote::None,
+    // 按 `steer` 的结果转向并移动 max_step
+    fn apply_drive(&mut self, rotation_y: f32, heading: Option<Vec2>, max_step: f32, delta: f32) {
+        self.rotation_y = rotation_y;
+        let Some(heading) = heading else {
+            self.velocity = Vec2::default();
+            return;
+        };
+
+        self.current.x += heading.x * max_step;
+        self.current.z += heading.z * max_step;
+        self.velocity = heading * (max_step / delta);
+    }
+
+    // 按运动模型朝 direction(单位向量)移动 max_step, 差速驱动朝向误差大于 align_tolerance 时只原地转向
+    fn drive(&mut self, direction: Vec2, align_tolerance: f32, max_step: f32, delta: f32) {
+        let (rotation_y, heading) = self.steer(direction, align_tolerance, max_step, delta);
+        self.apply_drive(rotation_y, heading, max_step, delta);
+    }
+
+    // 设置动作, 不允许的切换返回错误, 见 `animation`
+    pub fn set_action(&mut self, action: &str) -> Result<(), String> {
+        self.apply_action(RobotAction::parse(action)?)
+    }
+
+    pub fn apply_action(&mut self, action: RobotAction) -> Result<(), String> {
+        if !self.animation.set_action(action)? {
+            return Ok(());
+        }
+
+        self.speed = action.speed().unwrap_or(self.base_speed);
+
+        // 死亡后不再移动
+        if action == RobotAction::Death {
+            self.exploring = false;
+            self.clear_path();
         }
+
+        Ok(())
+    }
+
+    // 播放表情, 播放结束后回到当前动作
+    pub fn set_emote(&mut self, emote: &str) -> Result<(), String> {
+        self.animation.set_emote(RobotEmote::parse(emote)?)
+    }
+
+    pub fn get_animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    // 当前动作|表情是否锁定移动
+    pub fn is_movement_locked(&self) -> bool {
+        self.animation.locks_movement()
+    }
+
+    // 取出未推送的动作|表情切换
+    pub fn take_animation_transitions(&mut self) -> Vec<AnimationTransition> {
+        self.animation.take_transitions()
     }
 
     pub fn set_speed(&mut self, speed: f32) {
         self.speed = speed;
     }
 
+    // 生成时的速度
+    pub fn get_base_speed(&self) -> f32 {
+        self.base_speed
+    }
+
+    pub fn set_kinematics(&mut self, kinematics: Kinematics) -> Result<(), String> {
+        kinematics.validate()?;
+        self.kinematics = kinematics;
+        Ok(())
+    }
+
+    pub fn get_kinematics(&self) -> Kinematics {
+        self.kinematics
+    }
+
+    // 设置运动模型, 正在移动时按新的模型重新规划
+    pub fn set_motion_model(&mut self, grid: &mut Grid, motion: MotionModel) -> Result<(), String> {
+        motion.validate()?;
+        self.motion = motion;
+        if let Some(goal) = self.goal.filter(|_| self.is_moving) {
+            self.waiting = !self.plan(grid, goal);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_motion_model(&self) -> MotionModel {
+        self.motion
+    }
+
+    pub fn is_holonomic(&self) -> bool {
+        self.motion.is_holonomic()
+    }
+
+    pub fn set_tracking(&mut self, tracking: Tracking) -> Result<(), String> {
+        tracking.validate()?;
+        self.tracking = tracking;
+        Ok(())
+    }
+
+    pub fn get_tracking(&self) -> Tracking {
+        self.tracking
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn state(&self) -> RobotState {
+        RobotState {
+            id: self.id,
+            position: self.current,
+            is_moving: self.is_moving,
+            rotation_y: self.rotation_y,
+            path_index: self.path_index,
+            speed: self.linear_speed,
+            angular_velocity: self.angular_velocity,
+            cross_track_error: self.cross_track_error,
+            action: self.animation.action(),
+            emote: self.animation.emote(),
+        }
+    }
+
+    // 当前占用的格子
+    pub fn get_occupied(&self) -> &[GridPoint] {
+        &self.occupied
+    }
+
     pub fn get_current(&self) -> Vec3 {
         self.current
     }
 
-    pub fn get_point(&self) -> GridResultPoint {
-        Grid::grid_to_world(&GridPoint {
-            gx: self.current.x as i32,
-            gz: self.current.z as i32,
-        })
+    // 获取当前所在格子锚点的世界坐标
+    pub fn get_point(&self, grid: &Grid) -> ThreeGrid {
+        grid.cell_to_world(&grid.point_to_cell(self.current.x, self.current.z))
     }
 
     pub fn get_moving(&self) -> bool {
@@ -247,6 +1044,14 @@ impl Robot {
         self.rotation_y
     }
 
+    pub fn set_rotation_y(&mut self, rotation_y: f32) {
+        self.rotation_y = wrap_angle(rotation_y);
+    }
+
+    pub fn get_path(&self) -> &[Vec3] {
+        &self.path
+    }
+
     pub fn get_path_index(&self) -> usize {
         self.path_index
     }
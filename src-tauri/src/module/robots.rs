@@ -0,0 +1,515 @@
+/*!
+  机器人注册表
+
+  所有机器人按 id 管理, id 从 1 开始递增, 移除后不复用:
+  ```
+   - spawn: 在 (x, z) 生成机器人, 占用的格子被阻挡或被其它机器人占用时失败
+   - despawn: 移除机器人, 释放占用的格子
+   - update: 只更新一个机器人, 不推进世界时间
+   - tick: 推进世界时间(动态障碍物), 再按 id 顺序更新所有机器人
+  ```
+  开启避障时, 更新之前先用 ORCA 调整移动中机器人的速度, 见 `orca`
+  多个机器人同时设置目标时用 `plan_group` 统一规划, 每个机器人得到带时间的路径, 见 `mapf`
+  单个机器人设置目标时, 其它机器人正在移动则按它们的剩余路径预约格子, 规划出避开它们的带时间路径
+*/
+
+use crate::module::animation::AnimationTransition;
+use crate::module::behaviour::BehaviourStatus;
+use crate::module::dynamic::DynamicEvent;
+use crate::module::fog::BeliefCell;
+use crate::module::grid::{Grid, GridPoint};
+use crate::module::mapf::{self, MapfAgent, MapfSolver, ReservationTable};
+use crate::module::orca::{compute_velocity, Neighbor, Vec2};
+use crate::module::robot::{Robot, RobotState, Vec3};
+use crate::module::sensor::SensorReading;
+use crate::module::zone::ZoneEvent;
+use crate::{CHARACTER_OCCUPY_HEIGHT, CHARACTER_OCCUPY_WIDTH};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// 避障: 邻居机器人的搜索距离
+const NEIGHBOR_DIST: f32 = 10.0;
+
+// 避障: 机器人之间、机器人与障碍物之间的预测时间(秒)
+const TIME_HORIZON: f32 = 2.0;
+const OBSTACLE_TIME_HORIZON: f32 = 0.5;
+
+// 避障: 被挡住时期望速度向右偏的角度(弧度)
+const KEEP_RIGHT_ANGLE: f32 = 0.5;
+
+// 避障: 障碍物格子的半径, 略小于内切圆, 加上机器人半径后沿墙边走的路径不算重叠
+const OBSTACLE_RADIUS: f32 = 0.49;
+
+// 带时间的路径按最大速度的比例安排时间, 留出加速、减速的余量, 落后时可以追上
+const TIMED_SPEED_FACTOR: f32 = 0.8;
+
+// 按机器人分组的数据, 推送给前端
+#[derive(Serialize, Debug, Clone)]
+pub struct RobotData<T> {
+    #[serde(rename = "robotId")]
+    pub robot_id: u32,
+    pub data: T,
+}
+
+// 一次更新产生的状态和事件
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TickResult {
+    pub states: Vec<RobotState>,
+    #[serde(rename = "dynamicEvents")]
+    pub dynamic_events: Vec<DynamicEvent>,
+    #[serde(rename = "zoneEvents")]
+    pub zone_events: Vec<ZoneEvent>,
+    pub readings: Vec<RobotData<Vec<SensorReading>>>,
+    pub beliefs: Vec<RobotData<Vec<BeliefCell>>>,
+    pub explored: Vec<u32>, // 本次探索结束的机器人
+    pub animations: Vec<RobotData<Vec<AnimationTransition>>>,
+    pub behaviours: Vec<RobotData<BehaviourStatus>>, // 本次结束的行为树
+}
+
+// 多机器人规划的目标
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RobotGoal {
+    pub id: u32,
+    pub x: f32,
+    pub z: f32,
+}
+
+// 带时间的路径点, t 为到达的时间(秒)
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct TimedPoint {
+    pub x: f32,
+    pub z: f32,
+    pub t: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TimedPath {
+    #[serde(rename = "robotId")]
+    pub robot_id: u32,
+    pub points: Vec<TimedPoint>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RobotRegistry {
+    robots: BTreeMap<u32, Robot>,
+    next_id: u32,
+    avoidance: bool, // 局部避障
+}
+
+impl Default for RobotRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RobotRegistry {
+    pub fn new() -> Self {
+        Self {
+            robots: BTreeMap::new(),
+            next_id: 1,
+            avoidance: true,
+        }
+    }
+
+    // 用已有的机器人(回放存档)创建, 重新占用格子, 之后生成的机器人 id 从 next_id 开始
+    pub fn from_robots(grid: &mut Grid, robots: Vec<Robot>, next_id: u32, avoidance: bool) -> Self {
+        let mut registry = Self { next_id, avoidance, ..Self::new() };
+        for mut robot in robots {
+            robot.release_occupancy(grid);
+            robot.sync_occupancy(grid);
+            registry.next_id = registry.next_id.max(robot.get_id() + 1);
+            registry.robots.insert(robot.get_id(), robot);
+        }
+
+        registry
+    }
+
+    // 下一个生成的机器人 id
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    // 生成机器人
+    pub fn spawn(&mut self, grid: &mut Grid, x: f32, z: f32, speed: f32) -> Result<&Robot, String> {
+        let start = grid.point_to_cell(x - CHARACTER_OCCUPY_WIDTH / 2.0, z - CHARACTER_OCCUPY_HEIGHT / 2.0);
+        for dx in 0..CHARACTER_OCCUPY_WIDTH as i32 {
+            for dz in 0..CHARACTER_OCCUPY_HEIGHT as i32 {
+                let point = GridPoint { gx: start.gx + dx, gz: start.gz + dz };
+                if grid.is_blocked(point.gx, point.gz) || grid.get_cell(point.gx, point.gz).occupied {
+                    return Err(format!("cannot spawn robot at ({}, {}): cell ({}, {}) is not free", x, z, point.gx, point.gz));
+                }
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut robot = Robot::new(id, x, z, speed);
+        robot.sync_occupancy(grid);
+        info!("Robot {} spawned at ({}, {})", id, x, z);
+        Ok(self.robots.entry(id).or_insert(robot))
+    }
+
+    // 移除机器人
+    pub fn despawn(&mut self, grid: &mut Grid, id: u32) -> Result<Robot, String> {
+        let mut robot = self.robots.remove(&id).ok_or_else(|| format!("robot `{}` not found", id))?;
+        robot.release_occupancy(grid);
+        Ok(robot)
+    }
+
+    pub fn get(&self, id: u32) -> Result<&Robot, String> {
+        self.robots.get(&id).ok_or_else(|| format!("robot `{}` not found", id))
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Result<&mut Robot, String> {
+        self.robots.get_mut(&id).ok_or_else(|| format!("robot `{}` not found", id))
+    }
+
+    pub fn robots(&self) -> impl Iterator<Item = &Robot> {
+        self.robots.values()
+    }
+
+    pub fn robots_mut(&mut self) -> impl Iterator<Item = &mut Robot> {
+        self.robots.values_mut()
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.robots.keys().copied().collect()
+    }
+
+    pub fn states(&self) -> Vec<RobotState> {
+        self.robots.values().map(|robot| robot.state()).collect()
+    }
+
+    pub fn set_avoidance(&mut self, enabled: bool) {
+        self.avoidance = enabled;
+    }
+
+    pub fn is_avoidance(&self) -> bool {
+        self.avoidance
+    }
+
+    /**
+      多个机器人同时规划, 路径互不冲突
+      - 每个时间步移动一格, 时间步长按最慢的机器人走一格的时间(按各自路径上最慢的减速区)
+      - 不在 goals 中的机器人按障碍物处理(占用的格子)
+      - 只支持全向移动且没有战争迷雾的机器人, 其它机器人无法按格子逐步移动, 或不能经过还没看到的格子, 请单独设置目标
+    */
+    pub fn plan_group(&mut self, grid: &mut Grid, goals: &[RobotGoal], solver: MapfSolver) -> Result<Vec<TimedPath>, String> {
+        let mut agents = Vec::with_capacity(goals.len());
+        let mut min_speed = f32::INFINITY;
+        for (i, goal) in goals.iter().enumerate() {
+            if goals[..i].iter().any(|other| other.id == goal.id) {
+                return Err(format!("robot `{}` has more than one goal", goal.id));
+            }
+
+            let robot = self.get(goal.id)?;
+            if robot.is_movement_locked() {
+                return Err(format!("robot `{}` cannot move while {:?}", goal.id, robot.get_animation().action()));
+            }
+
+            if !robot.is_holonomic() || robot.get_belief().is_some() {
+                return Err(format!("robot `{}` must be holonomic without fog to join a group plan", goal.id));
+            }
+
+            let current = robot.get_current();
+            agents.push(MapfAgent {
+                start: grid.point_to_cell(current.x, current.z),
+                goal: grid.point_to_cell(goal.x, goal.z),
+            });
+            min_speed = min_speed.min(robot.get_speed());
+        }
+
+        if agents.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if min_speed <= 0.0 {
+            return Err("robot speed must be positive".to_string());
+        }
+
+        // 规划时先释放这些机器人占用的格子, 否则会挡住自己
+        for goal in goals {
+            if let Some(robot) = self.robots.get_mut(&goal.id) {
+                robot.release_occupancy(grid);
+            }
+        }
+
+        let solution = mapf::solve(grid, &agents, solver);
+
+        for goal in goals {
+            if let Some(robot) = self.robots.get_mut(&goal.id) {
+                robot.sync_occupancy(grid);
+            }
+        }
+
+        let solution = solution?;
+        let min_speed = goals
+            .iter()
+            .zip(&solution)
+            .filter_map(|(goal, cells)| self.robots.get(&goal.id).map(|robot| robot.get_speed() * Self::slowest_factor(grid, cells)))
+            .fold(f32::INFINITY, f32::min);
+        if min_speed <= 0.0 {
+            return Err("robot speed must be positive".to_string());
+        }
+
+        let step = grid.coords().cell_size / (min_speed * TIMED_SPEED_FACTOR);
+        let mut paths = Vec::with_capacity(goals.len());
+        for (goal, cells) in goals.iter().zip(solution) {
+            let points = Self::timed_points(grid, &cells, step);
+            if let Some(robot) = self.robots.get_mut(&goal.id) {
+                Self::apply_timed_path(robot, &points);
+            }
+
+            paths.push(TimedPath { robot_id: goal.id, points });
+        }
+
+        info!("Planned {} robots with {:?}", paths.len(), solver);
+        Ok(paths)
+    }
+
+    // 路径经过的格子中最小的速度倍率(减速区)
+    fn slowest_factor(grid: &Grid, cells: &[GridPoint]) -> f32 {
+        cells
+            .iter()
+            .map(|cell| {
+                let point = grid.cell_to_world(cell);
+                grid.speed_factor(point.x, point.z)
+            })
+            .fold(1.0, f32::min)
+    }
+
+    /**
+      设置目标, 避开其它机器人之后经过的格子
+      - 其它正在移动的机器人按剩余路径和速度写入预约表(时空预约), 到达后停在终点
+      - 时空搜索按格子代价(减速区、代价区)规划, 每个时间步一格, 不做路径平滑
+      - 没有机器人在移动、迷雾模式、非全向移动(需要按运动模型规划)或避开预约无解时, 同 `Robot::set_target`
+    */
+    pub fn set_target(&mut self, grid: &mut Grid, id: u32, x: f32, z: f32) -> Result<Vec<Vec3>, String> {
+        let robot = self.get(id)?;
+        if robot.is_movement_locked() {
+            return Err(format!("robot `{}` cannot move while {:?}", id, robot.get_animation().action()));
+        }
+
+        let speed = robot.max_speed(grid);
+        let moving: Vec<u32> = self.robots.values().filter(|other| other.get_id() != id && other.get_moving()).map(|other| other.get_id()).collect();
+        if moving.is_empty() || robot.get_belief().is_some() || !robot.is_holonomic() || speed <= 0.0 {
+            return Ok(self.get_mut(id)?.set_target(grid, x, z));
+        }
+
+        let step = grid.coords().cell_size / (speed * TIMED_SPEED_FACTOR);
+        let mut table = ReservationTable::new();
+        for other in &moving {
+            table.reserve(&self.robots[other].predict_cells(grid, step), true);
+        }
+
+        let current = robot.get_current();
+        let agent = MapfAgent {
+            start: grid.point_to_cell(current.x, current.z),
+            goal: grid.point_to_cell(x, z),
+        };
+
+        // 移动中的机器人已经在预约表中, 规划时释放它们和自己占用的格子
+        for other in moving.iter().chain([&id]) {
+            if let Some(robot) = self.robots.get_mut(other) {
+                robot.release_occupancy(grid);
+            }
+        }
+
+        let cells = mapf::plan_reserved(grid, &agent, &table);
+
+        for other in moving.iter().chain([&id]) {
+            if let Some(robot) = self.robots.get_mut(other) {
+                robot.sync_occupancy(grid);
+            }
+        }
+
+        let robot = self.get_mut(id)?;
+        match cells {
+            Ok(cells) => {
+                Self::apply_timed_path(robot, &Self::timed_points(grid, &cells, step));
+                Ok(robot.get_path().to_vec())
+            }
+            Err(err) => {
+                info!("Robot {} reservation planning failed: {}", id, err);
+                Ok(robot.set_target(grid, x, z))
+            }
+        }
+    }
+
+    // 时间步格子 → 带时间的世界坐标, step 为每个时间步的秒数
+    fn timed_points(grid: &Grid, cells: &[GridPoint], step: f32) -> Vec<TimedPoint> {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(t, cell)| {
+                let world = grid.cell_to_world(cell);
+                TimedPoint { x: world.x, z: world.z, t: t as f32 * step }
+            })
+            .collect()
+    }
+
+    fn apply_timed_path(robot: &mut Robot, points: &[TimedPoint]) {
+        robot.set_timed_path(points.iter().map(|p| Vec3 { x: p.x, y: 0.0, z: p.z }).collect(), points.iter().map(|p| p.t).collect());
+    }
+
+    // 只更新一个机器人
+    pub fn update(&mut self, grid: &mut Grid, id: u32, delta: f32) -> Result<TickResult, String> {
+        self.get(id)?;
+        self.run_behaviour(grid, id, delta);
+        if self.avoidance {
+            self.avoid(grid, delta, Some(id));
+        }
+
+        let robot = self.robots.get_mut(&id).ok_or_else(|| format!("robot `{}` not found", id))?;
+        let mut result = TickResult::default();
+        Self::update_robot(robot, grid, delta, &mut result);
+        Ok(result)
+    }
+
+    // 推进世界时间并更新所有机器人
+    pub fn tick(&mut self, grid: &mut Grid, delta: f32) -> TickResult {
+        let mut result = TickResult {
+            dynamic_events: grid.advance(delta),
+            ..TickResult::default()
+        };
+
+        let ids: Vec<u32> = self.robots.keys().copied().collect();
+        for id in ids {
+            self.run_behaviour(grid, id, delta);
+        }
+
+        if self.avoidance {
+            self.avoid(grid, delta, None);
+        }
+
+        for robot in self.robots.values_mut() {
+            Self::update_robot(robot, grid, delta, &mut result);
+        }
+
+        result
+    }
+
+    // 执行机器人的行为树, 行为树请求的目标按 `set_target` 规划(避开其它机器人的预约)
+    fn run_behaviour(&mut self, grid: &mut Grid, id: u32, delta: f32) {
+        let Some(robot) = self.robots.get_mut(&id) else {
+            return;
+        };
+
+        robot.run_behaviour(grid, delta);
+        if let Some(target) = robot.take_target_request() {
+            if let Err(err) = self.set_target(grid, id, target.x, target.z) {
+                info!("Robot {} behaviour target failed: {}", id, err);
+            }
+        }
+    }
+
+    // ORCA 避障, only 为空时处理所有机器人, 所有速度按同一时刻的状态计算
+    fn avoid(&mut self, grid: &Grid, delta: f32, only: Option<u32>) {
+        let radius = CHARACTER_OCCUPY_WIDTH / 2.0;
+        let agents: Vec<(u32, Vec2, Vec2, Vec2, f32)> = self
+            .robots
+            .values()
+            .map(|robot| {
+                let current = robot.get_current();
+                (robot.get_id(), Vec2::new(current.x, current.z), robot.get_velocity(), robot.preferred_velocity(grid, delta), robot.max_speed(grid))
+            })
+            .collect();
+
+        for &(id, position, velocity, preferred, max_speed) in &agents {
+            if only.is_some_and(|only| only != id) || preferred.length_sq() == 0.0 {
+                continue;
+            }
+
+            // 带时间的路径已经互不冲突, 按计划走, 落后于计划时可能与其它机器人相遇, 仍参与避让; 非全向移动的机器人无法按避障速度移动; 其它机器人仍会避开它们
+            if self.robots.get(&id).is_some_and(|robot| (robot.is_timed() && !robot.is_behind_schedule()) || !robot.is_holonomic()) {
+                continue;
+            }
+
+            let mut neighbors = Vec::new();
+            for &(other, other_position, other_velocity, other_preferred, _) in &agents {
+                if other == id || (other_position - position).length() > NEIGHBOR_DIST {
+                    continue;
+                }
+
+                // 静止的机器人不会让路, 由自己承担全部
+                let moving = other_preferred.length_sq() > 0.0;
+                neighbors.push(Neighbor {
+                    position: other_position,
+                    velocity: other_velocity,
+                    radius: radius * 2.0,
+                    responsibility: if moving { 0.5 } else { 1.0 },
+                    time_horizon: TIME_HORIZON,
+                });
+            }
+
+            // 附近的阻挡格子
+            let reach = max_speed * OBSTACLE_TIME_HORIZON + radius + OBSTACLE_RADIUS;
+            let center = grid.point_to_cell(position.x, position.z);
+            let cells = reach.ceil() as i32 + 1;
+            for gx in (center.gx - cells)..=(center.gx + cells) {
+                for gz in (center.gz - cells)..=(center.gz + cells) {
+                    let cell_center = grid.cell_center(gx, gz);
+                    let cell_position = Vec2::new(cell_center.x, cell_center.z);
+                    if (cell_position - position).length() > reach + 1.0 || !grid.is_blocked(gx, gz) {
+                        continue;
+                    }
+
+                    neighbors.push(Neighbor {
+                        position: cell_position,
+                        velocity: Vec2::default(),
+                        radius: radius + OBSTACLE_RADIUS,
+                        responsibility: 1.0,
+                        time_horizon: OBSTACLE_TIME_HORIZON,
+                    });
+                }
+            }
+
+            let mut new_velocity = compute_velocity(position, velocity, preferred, max_speed, &neighbors, delta);
+
+            // 正面相遇时双方对称, 可能都停下来, 此时统一向右偏, 打破对称
+            if new_velocity.length_sq() < preferred.length_sq() * 0.25 {
+                let (sin, cos) = KEEP_RIGHT_ANGLE.sin_cos();
+                let right = Vec2::new(preferred.x * cos + preferred.z * sin, -preferred.x * sin + preferred.z * cos);
+                new_velocity = compute_velocity(position, velocity, right, max_speed, &neighbors, delta);
+            }
+
+            if let Some(robot) = self.robots.get_mut(&id) {
+                robot.set_avoidance(new_velocity);
+            }
+        }
+    }
+
+    fn update_robot(robot: &mut Robot, grid: &mut Grid, delta: f32, result: &mut TickResult) {
+        let exploring = robot.is_exploring();
+        result.zone_events.extend(robot.update(grid, delta));
+
+        let changes = robot.take_belief_changes();
+        if !changes.is_empty() {
+            result.beliefs.push(RobotData { robot_id: robot.get_id(), data: changes });
+        }
+
+        if exploring && !robot.is_exploring() {
+            result.explored.push(robot.get_id());
+        }
+
+        if let Some(status) = robot.take_behaviour_result() {
+            result.behaviours.push(RobotData { robot_id: robot.get_id(), data: status });
+        }
+
+        let transitions = robot.take_animation_transitions();
+        if !transitions.is_empty() {
+            result.animations.push(RobotData { robot_id: robot.get_id(), data: transitions });
+        }
+
+        if robot.take_readings_changed() && !robot.get_readings().is_empty() {
+            result.readings.push(RobotData {
+                robot_id: robot.get_id(),
+                data: robot.get_readings().to_vec(),
+            });
+        }
+
+        result.states.push(robot.state());
+    }
+}
@@ -0,0 +1,531 @@
+/*!
+  机器人脚本(Rhai)
+
+  每个机器人可以运行一个脚本, 脚本在独立的线程中执行, 调用机器人 API 时把请求发给模拟线程并等待回复:
+  ```
+   - 让出(协程): move_to(name) | move_to(x, z) | wait(secs) | next_tick() | emote(name), 条件满足后的 tick 中才回复
+   - 立即回复: action(name) | position() | is_moving() | marker(name) | raycast(dx, dz, max) | can_see(name) | obstacle_at(x, z) | is_blocked(x, z)
+   - 常量 ID 为机器人 id, print | debug 的输出推送给前端
+  ```
+  请求只在模拟线程中按机器人 id 的顺序处理, 每次 tick 执行到下一次让出为止, 访问世界的顺序与线程调度无关:
+  ```
+   - begin: 选出本次 tick 推进的脚本
+   - wait: 等待脚本发来下一个请求, 不访问世界, 调用时不持有 robots、grid 的锁
+   - step: 处理已收到的请求, 还有脚本在等待回复时再次 wait
+  ```
+  脚本产生的目标、动作、表情作为输入返回, 录制时写入录制文件, 回放不需要脚本
+  沙箱:
+  ```
+   - 禁用 eval 和 import, 限制调用深度、表达式深度、字符串|数组|对象大小
+   - 两次让出之间最多执行 MAX_OPERATIONS 次操作, 超过 SCRIPT_TIMEOUT 没有发来请求时终止
+  ```
+  语法错误在启动时返回, 运行中的错误、结束通过 `script` 事件推送给前端
+*/
+
+use crate::error::Error;
+use crate::module::animation::RobotEmote;
+use crate::module::grid::{Grid, ThreeGrid};
+use crate::module::raycast::{clamp_ray_distance, line_of_sight, raycast, RayMask};
+use crate::module::robot::Robot;
+use crate::module::robots::{RobotData, RobotRegistry};
+use crate::module::session::SessionInput;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Position, Scope};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// 离目标小于该距离时 move_to 算作到达
+const ARRIVE_DISTANCE: f32 = 1.5;
+
+// wait 最长等待的秒数, 太大时每次减去 delta 不会变化
+const MAX_WAIT_SECS: f32 = 3600.0;
+
+// 两次让出之间最多执行的操作数
+const MAX_OPERATIONS: u64 = 100_000;
+
+// 等待脚本发来请求的最长时间
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 推送给前端的脚本事件
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ScriptEvent {
+    Log { message: String },
+    Finished,
+    Error { message: String },
+}
+
+// 一次 tick 中脚本产生的事件和输入
+#[derive(Debug, Default)]
+pub struct ScriptOutput {
+    pub events: Vec<RobotData<ScriptEvent>>,
+    pub inputs: Vec<SessionInput>,
+}
+
+// 脚本线程 → 模拟线程
+#[derive(Debug)]
+enum ScriptRequest {
+    MoveTo { x: f32, z: f32 },
+    MoveToMarker(String),
+    Wait(f32),
+    NextTick,
+    Emote(String),
+    Action(String),
+    Position,
+    IsMoving,
+    Marker(String),
+    Raycast { dx: f32, dz: f32, max: f32 },
+    CanSee(String),
+    ObstacleAt { x: f32, z: f32 },
+    IsBlocked { x: f32, z: f32 },
+    Log(String),
+    Finished(Result<(), String>),
+}
+
+type ScriptReply = Result<Value, String>;
+
+// 让出中的请求, 条件满足后回复
+#[derive(Debug)]
+enum Pending {
+    Move { x: f32, z: f32 },
+    Wait(f32), // 剩余秒数
+    NextTick,
+    Emote,
+}
+
+// 执行请求的结果
+enum Step {
+    Reply(ScriptReply),
+    Yield(Pending),
+}
+
+// 本次 tick 中脚本的状态
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Turn {
+    #[default]
+    Idle, // 已让出或本次 tick 不推进
+    Resume,   // 需要检查让出的条件
+    Awaiting, // 等待脚本发来下一个请求
+}
+
+// 模拟线程持有的脚本
+struct RobotScript {
+    requests: Receiver<ScriptRequest>,
+    replies: Sender<ScriptReply>,
+    cancelled: Arc<AtomicBool>,
+    pending: Option<Pending>,
+    turn: Turn,
+    inbox: Vec<ScriptRequest>,   // wait 收到、尚未处理的请求
+    failed: Option<ScriptEvent>, // wait 中超时或脚本线程退出
+}
+
+// 脚本线程持有的通道
+struct ScriptChannel {
+    requests: Sender<ScriptRequest>,
+    replies: Receiver<ScriptReply>,
+    operations: Cell<u64>, // 已执行的操作数
+    yielded: Cell<u64>,    // 上次让出时的操作数
+}
+
+#[derive(Default)]
+pub struct ScriptHost {
+    scripts: BTreeMap<u32, RobotScript>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 在机器人上运行脚本, 替换正在运行的脚本, 语法错误返回错误
+    pub fn start(&mut self, id: u32, source: &str) -> Result<(), String> {
+        sandbox().compile(source).map_err(|err| err.to_string())?;
+
+        let (request_tx, request_rx) = mpsc::channel();
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let source = source.to_string();
+        let flag = cancelled.clone();
+        thread::Builder::new()
+            .name(format!("robot-script-{}", id))
+            .spawn(move || run(id, &source, request_tx, reply_rx, flag))
+            .map_err(|err| Error::Error(err.to_string()).to_string())?;
+
+        self.scripts.insert(
+            id,
+            RobotScript {
+                requests: request_rx,
+                replies: reply_tx,
+                cancelled,
+                pending: None,
+                turn: Turn::Idle,
+                inbox: Vec::new(),
+                failed: None,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn load(&mut self, id: u32, path: &Path) -> Result<(), String> {
+        let source = std::fs::read_to_string(path).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        self.start(id, &source)
+    }
+
+    pub fn stop(&mut self, id: u32) -> Result<(), String> {
+        self.scripts.remove(&id).map(|_| ()).ok_or_else(|| format!("robot `{}` has no script", id))
+    }
+
+    // 正在运行脚本的机器人
+    pub fn ids(&self) -> Vec<u32> {
+        self.scripts.keys().copied().collect()
+    }
+
+    // 开始一次 tick, only 不为空时只推进该机器人的脚本
+    pub fn begin(&mut self, only: Option<u32>) {
+        for (id, script) in self.scripts.iter_mut() {
+            script.turn = match only.is_some_and(|only| only != *id) {
+                true => Turn::Idle,
+                false if script.pending.is_some() => Turn::Resume,
+                false => Turn::Awaiting,
+            };
+        }
+    }
+
+    // 等待脚本发来下一个请求, 不访问世界
+    pub fn wait(&mut self) {
+        for script in self.scripts.values_mut() {
+            script.wait();
+        }
+    }
+
+    // 处理已收到的请求, 返回 true 表示还有脚本在等待, 需要再次 wait
+    pub fn step(&mut self, grid: &mut Grid, robots: &mut RobotRegistry, delta: f32, output: &mut ScriptOutput) -> bool {
+        self.scripts.retain(|id, script| script.step(*id, grid, robots, delta, output));
+        self.scripts.values().any(|script| script.turn == Turn::Awaiting)
+    }
+}
+
+impl ScriptOutput {
+    fn event(&mut self, id: u32, event: ScriptEvent) {
+        self.events.push(RobotData { robot_id: id, data: event });
+    }
+}
+
+impl Drop for RobotScript {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl RobotScript {
+    // 收到下一个请求为止, 输出先放入 inbox
+    fn wait(&mut self) {
+        if self.turn != Turn::Awaiting || self.failed.is_some() || self.inbox.last().is_some_and(|request| !matches!(request, ScriptRequest::Log(_))) {
+            return;
+        }
+
+        loop {
+            match self.requests.recv_timeout(SCRIPT_TIMEOUT) {
+                Ok(ScriptRequest::Log(message)) => self.inbox.push(ScriptRequest::Log(message)),
+                Ok(request) => {
+                    self.inbox.push(request);
+                    return;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.failed = Some(ScriptEvent::Error {
+                        message: format!("script did not yield within {:?}", SCRIPT_TIMEOUT),
+                    });
+                    return;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.failed = Some(ScriptEvent::Error {
+                        message: "script stopped unexpectedly".to_string(),
+                    });
+                    return;
+                }
+            }
+        }
+    }
+
+    // 处理已收到的请求, 返回 false 表示脚本已结束
+    fn step(&mut self, id: u32, grid: &mut Grid, robots: &mut RobotRegistry, delta: f32, output: &mut ScriptOutput) -> bool {
+        if self.turn == Turn::Idle {
+            return true;
+        }
+
+        let Ok(robot) = robots.get(id) else {
+            output.event(id, ScriptEvent::Error { message: format!("robot `{}` not found", id) });
+            return false;
+        };
+
+        if self.turn == Turn::Resume {
+            self.turn = Turn::Awaiting;
+            if let Some(mut pending) = self.pending.take() {
+                match resume(&mut pending, robot, delta) {
+                    Some(reply) => {
+                        let _ = self.replies.send(reply);
+                    }
+                    None => {
+                        self.pending = Some(pending);
+                        self.turn = Turn::Idle;
+                    }
+                }
+            }
+            return true;
+        }
+
+        for request in std::mem::take(&mut self.inbox) {
+            let step = match request {
+                ScriptRequest::Log(message) => {
+                    output.event(id, ScriptEvent::Log { message });
+                    continue;
+                }
+                ScriptRequest::Finished(result) => {
+                    output.event(id, result.map_or_else(|message| ScriptEvent::Error { message }, |_| ScriptEvent::Finished));
+                    return false;
+                }
+                request => execute(id, request, grid, robots, &mut output.inputs),
+            };
+
+            match step {
+                Step::Reply(reply) => {
+                    let _ = self.replies.send(reply);
+                }
+                Step::Yield(pending) => {
+                    self.pending = Some(pending);
+                    self.turn = Turn::Idle;
+                }
+            }
+        }
+
+        match self.failed.take() {
+            Some(event) => {
+                output.event(id, event);
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+// 执行请求, 修改机器人的请求写入 inputs
+fn execute(id: u32, request: ScriptRequest, grid: &mut Grid, robots: &mut RobotRegistry, inputs: &mut Vec<SessionInput>) -> Step {
+    let Ok(robot) = robots.get(id) else {
+        return Step::Reply(Err(format!("robot `{}` not found", id)));
+    };
+    let current = robot.get_current();
+    let from = ThreeGrid { x: current.x, z: current.z };
+
+    match request {
+        ScriptRequest::MoveTo { x, z } => move_to(id, x, z, grid, robots, inputs),
+        ScriptRequest::MoveToMarker(name) => match grid.get_marker_by_name(&name) {
+            Some(marker) => {
+                let (x, z) = (marker.x, marker.z);
+                move_to(id, x, z, grid, robots, inputs)
+            }
+            None => Step::Reply(Err(format!("marker `{}` not found", name))),
+        },
+        ScriptRequest::Wait(secs) if !(0.0..=MAX_WAIT_SECS).contains(&secs) => Step::Reply(Err(format!("wait secs must be between 0 and {}", MAX_WAIT_SECS))),
+        ScriptRequest::Wait(secs) => Step::Yield(Pending::Wait(secs)),
+        ScriptRequest::NextTick => Step::Yield(Pending::NextTick),
+        ScriptRequest::Emote(emote) => match robots.get_mut(id).and_then(|robot| robot.set_emote(&emote)) {
+            Ok(()) => {
+                inputs.push(SessionInput::Emote { id, emote });
+                Step::Yield(Pending::Emote)
+            }
+            Err(err) => Step::Reply(Err(err)),
+        },
+        ScriptRequest::Action(action) => Step::Reply(robots.get_mut(id).and_then(|robot| robot.set_action(&action)).map(|_| {
+            inputs.push(SessionInput::Action { id, action });
+            Value::Null
+        })),
+        ScriptRequest::Position => Step::Reply(Ok(json!({ "x": current.x, "z": current.z }))),
+        ScriptRequest::IsMoving => Step::Reply(Ok(Value::Bool(robot.get_moving()))),
+        ScriptRequest::Marker(name) => Step::Reply(to_value(grid.get_marker_by_name(&name))),
+        ScriptRequest::Raycast { dx, dz, max } => {
+            if !(dx.is_finite() && dz.is_finite()) {
+                return Step::Reply(Err("ray direction must be finite".to_string()));
+            }
+            match clamp_ray_distance(grid, max) {
+                Ok(max) => Step::Reply(to_value(raycast(grid, from, ThreeGrid { x: dx, z: dz }, max, RayMask::Sight))),
+                Err(err) => Step::Reply(Err(err)),
+            }
+        }
+        ScriptRequest::CanSee(name) => match grid.get_marker_by_name(&name) {
+            Some(marker) => Step::Reply(Ok(Value::Bool(line_of_sight(grid, from, ThreeGrid { x: marker.x, z: marker.z }, RayMask::Sight)))),
+            None => Step::Reply(Err(format!("marker `{}` not found", name))),
+        },
+        ScriptRequest::ObstacleAt { x, z } => Step::Reply(to_value(grid.get_obstacle_at(x, z))),
+        ScriptRequest::IsBlocked { x, z } => {
+            let cell = grid.point_to_cell(x, z);
+            Step::Reply(Ok(Value::Bool(grid.is_blocked(cell.gx, cell.gz))))
+        }
+        ScriptRequest::Log(_) | ScriptRequest::Finished(_) => Step::Reply(Ok(Value::Null)),
+    }
+}
+
+// 前往 (x, z), 已经到达或无法到达时立即回复
+fn move_to(id: u32, x: f32, z: f32, grid: &mut Grid, robots: &mut RobotRegistry, inputs: &mut Vec<SessionInput>) -> Step {
+    if robots.get(id).is_ok_and(|robot| arrived(robot, x, z)) {
+        return Step::Reply(Ok(Value::Bool(true)));
+    }
+
+    if let Err(err) = robots.set_target(grid, id, x, z) {
+        return Step::Reply(Err(err));
+    }
+
+    inputs.push(SessionInput::Target { id, x, z });
+    match robots.get(id) {
+        Ok(robot) if robot.get_moving() => Step::Yield(Pending::Move { x, z }),
+        _ => Step::Reply(Ok(Value::Bool(false))),
+    }
+}
+
+// 检查让出的条件, 满足时返回回复
+fn resume(pending: &mut Pending, robot: &Robot, delta: f32) -> Option<ScriptReply> {
+    match pending {
+        Pending::Move { x, z } => (!robot.get_moving()).then(|| Ok(Value::Bool(arrived(robot, *x, *z)))),
+        Pending::Wait(remaining) => {
+            *remaining -= delta;
+            (*remaining <= 0.0).then_some(Ok(Value::Null))
+        }
+        Pending::NextTick => Some(Ok(Value::Null)),
+        Pending::Emote => (robot.get_animation().emote() == RobotEmote::None).then_some(Ok(Value::Null)),
+    }
+}
+
+fn arrived(robot: &Robot, x: f32, z: f32) -> bool {
+    let current = robot.get_current();
+    ((x - current.x).powi(2) + (z - current.z).powi(2)).sqrt() <= ARRIVE_DISTANCE
+}
+
+fn to_value<T: Serialize>(value: T) -> ScriptReply {
+    serde_json::to_value(value).map_err(|err| err.to_string())
+}
+
+// 受限的脚本引擎
+fn sandbox() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .disable_symbol("eval")
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_modules(0)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(10_000)
+        .set_max_array_size(10_000)
+        .set_max_map_size(1_000);
+    engine
+}
+
+// 脚本线程
+fn run(id: u32, source: &str, requests: Sender<ScriptRequest>, replies: Receiver<ScriptReply>, cancelled: Arc<AtomicBool>) {
+    let channel = Rc::new(ScriptChannel {
+        requests,
+        replies,
+        operations: Cell::new(0),
+        yielded: Cell::new(0),
+    });
+
+    let mut engine = sandbox();
+    let progress = channel.clone();
+    engine.on_progress(move |operations| {
+        progress.operations.set(operations);
+        if cancelled.load(Ordering::Relaxed) {
+            Some(Dynamic::from("script stopped"))
+        } else if operations - progress.yielded.get() > MAX_OPERATIONS {
+            Some(Dynamic::from(format!("script exceeded {} operations without yielding", MAX_OPERATIONS)))
+        } else {
+            None
+        }
+    });
+
+    let log = channel.clone();
+    engine.on_print(move |text| {
+        let _ = log.requests.send(ScriptRequest::Log(text.to_string()));
+    });
+    let log = channel.clone();
+    engine.on_debug(move |text, _, position: Position| {
+        let _ = log.requests.send(ScriptRequest::Log(format!("{} {}", position, text)));
+    });
+
+    register(&mut engine, &channel);
+
+    let mut scope = Scope::new();
+    scope.push_constant("ID", id as rhai::INT);
+    let result = engine.run_with_scope(&mut scope, source).map_err(|err| match *err {
+        EvalAltResult::ErrorTerminated(reason, _) => reason.to_string(),
+        err => err.to_string(),
+    });
+    let _ = channel.requests.send(ScriptRequest::Finished(result));
+}
+
+impl ScriptChannel {
+    // 发送请求并等待回复, yields 为 true 时重新开始计算操作数
+    fn call(&self, request: ScriptRequest, yields: bool) -> Result<Dynamic, Box<EvalAltResult>> {
+        self.requests.send(request).map_err(|_| "script stopped")?;
+        let reply = self.replies.recv().map_err(|_| "script stopped")?;
+        if yields {
+            self.yielded.set(self.operations.get());
+        }
+
+        rhai::serde::to_dynamic(reply?)
+    }
+}
+
+// 脚本参数中的数字, 整数和浮点数都可以
+fn number(value: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    if let Ok(value) = value.as_float() {
+        return Ok(value as f32);
+    }
+
+    value.as_int().map(|value| value as f32).map_err(|_| format!("expected a number, got {}", value.type_name()).into())
+}
+
+// 注册机器人 API
+fn register(engine: &mut Engine, channel: &Rc<ScriptChannel>) {
+    let c = channel.clone();
+    engine.register_fn("move_to", move |name: &str| c.call(ScriptRequest::MoveToMarker(name.to_string()), true));
+    let c = channel.clone();
+    engine.register_fn("move_to", move |x: Dynamic, z: Dynamic| c.call(ScriptRequest::MoveTo { x: number(&x)?, z: number(&z)? }, true));
+    let c = channel.clone();
+    engine.register_fn("wait", move |secs: Dynamic| c.call(ScriptRequest::Wait(number(&secs)?), true));
+    let c = channel.clone();
+    engine.register_fn("next_tick", move || c.call(ScriptRequest::NextTick, true));
+    let c = channel.clone();
+    engine.register_fn("emote", move |name: &str| c.call(ScriptRequest::Emote(name.to_string()), true));
+    let c = channel.clone();
+    engine.register_fn("action", move |name: &str| c.call(ScriptRequest::Action(name.to_string()), false));
+    let c = channel.clone();
+    engine.register_fn("position", move || c.call(ScriptRequest::Position, false));
+    let c = channel.clone();
+    engine.register_fn("is_moving", move || c.call(ScriptRequest::IsMoving, false));
+    let c = channel.clone();
+    engine.register_fn("marker", move |name: &str| c.call(ScriptRequest::Marker(name.to_string()), false));
+    let c = channel.clone();
+    engine.register_fn("raycast", move |dx: Dynamic, dz: Dynamic, max: Dynamic| {
+        c.call(
+            ScriptRequest::Raycast {
+                dx: number(&dx)?,
+                dz: number(&dz)?,
+                max: number(&max)?,
+            },
+            false,
+        )
+    });
+    let c = channel.clone();
+    engine.register_fn("can_see", move |name: &str| c.call(ScriptRequest::CanSee(name.to_string()), false));
+    let c = channel.clone();
+    engine.register_fn("obstacle_at", move |x: Dynamic, z: Dynamic| c.call(ScriptRequest::ObstacleAt { x: number(&x)?, z: number(&z)? }, false));
+    let c = channel.clone();
+    engine.register_fn("is_blocked", move |x: Dynamic, z: Dynamic| c.call(ScriptRequest::IsBlocked { x: number(&x)?, z: number(&z)? }, false));
+}
@@ -0,0 +1,199 @@
+/*!
+  机器人传感器: 激光雷达、接近传感器、碰撞传感器
+
+  每次 `Robot::update` 后从机器人当前位置、朝向发射射线, 只检测实体障碍物(`RayMask::Solid`):
+  ```
+   - lidar: 在 fov 范围内均匀发射 rays 条射线, 返回每条射线的距离(没碰到返回 range), 可加均匀噪声 ±noise
+   - proximity: 沿 angle 方向发射一条射线, 返回最近障碍物的距离和 id
+   - bump: 沿 angle 方向, 障碍物距离机器人边缘小于 BUMP_DISTANCE 时触发
+  ```
+  angle 相对机器人朝向(rotation_y), 0 为正前方, 与 Robot 一致: 方向 = (sin, cos)
+*/
+
+use crate::error::Error;
+use crate::module::grid::{Grid, ThreeGrid};
+use crate::module::raycast::{raycast, RayMask};
+use crate::CHARACTER_OCCUPY_WIDTH;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+// 碰撞传感器触发距离(从机器人边缘算起)
+const BUMP_DISTANCE: f32 = 0.25;
+
+// 激光雷达最多射线数、传感器最大距离, 每次 update 都要逐条射线检测
+const MAX_RAYS: u32 = 360;
+const MAX_RANGE: f32 = 100.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SensorConfig {
+    Lidar {
+        name: String,
+        rays: u32,
+        range: f32,
+        #[serde(default = "default_fov")]
+        fov: f32, // 弧度, 默认 360°
+        #[serde(default)]
+        noise: f32,
+    },
+    Proximity {
+        name: String,
+        #[serde(default)]
+        angle: f32,
+        range: f32,
+    },
+    Bump {
+        name: String,
+        #[serde(default)]
+        angle: f32,
+    },
+}
+
+fn default_fov() -> f32 {
+    TAU
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SensorReading {
+    Lidar {
+        name: String,
+        #[serde(rename = "angleMin")]
+        angle_min: f32, // 第一条射线的绝对角度
+        #[serde(rename = "angleStep")]
+        angle_step: f32,
+        ranges: Vec<f32>,
+    },
+    Proximity {
+        name: String,
+        distance: Option<f32>,
+        #[serde(rename = "obstacleId")]
+        obstacle_id: Option<u32>,
+    },
+    Bump {
+        name: String,
+        pressed: bool,
+    },
+}
+
+impl SensorConfig {
+    // 默认配置: 36 线激光雷达 + 前方接近传感器 + 前方碰撞传感器
+    pub fn defaults() -> Vec<SensorConfig> {
+        vec![
+            SensorConfig::Lidar {
+                name: "lidar".to_string(),
+                rays: 36,
+                range: 10.0,
+                fov: TAU,
+                noise: 0.0,
+            },
+            SensorConfig::Proximity {
+                name: "front".to_string(),
+                angle: 0.0,
+                range: 3.0,
+            },
+            SensorConfig::Bump { name: "bumper".to_string(), angle: 0.0 },
+        ]
+    }
+
+    /**
+      校验传感器参数
+      ```
+       - range: 有限值, (0, 100]
+       - rays: 1 ~ 360
+       - fov: 有限值, (0, 2π]
+       - noise: 有限值, 不小于 0
+       - angle: 有限值
+      ```
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        let range_ok = |range: f32| range.is_finite() && range > 0.0 && range <= MAX_RANGE;
+        let valid = match *self {
+            SensorConfig::Lidar { rays, range, fov, noise, .. } => (1..=MAX_RAYS).contains(&rays) && range_ok(range) && fov.is_finite() && fov > 0.0 && fov <= TAU && noise.is_finite() && noise >= 0.0,
+            SensorConfig::Proximity { angle, range, .. } => angle.is_finite() && range_ok(range),
+            SensorConfig::Bump { angle, .. } => angle.is_finite(),
+        };
+
+        if !valid {
+            return Err(Error::convert_string(&format!("sensor `{}` has invalid parameters", self.name())));
+        }
+
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            SensorConfig::Lidar { name, .. } | SensorConfig::Proximity { name, .. } | SensorConfig::Bump { name, .. } => name,
+        }
+    }
+
+    // 传感器覆盖的射线 (绝对角度, 距离), 用于认知地图
+    pub fn rays(&self, heading: f32) -> Vec<(f32, f32)> {
+        match self {
+            SensorConfig::Lidar { rays, range, fov, .. } => {
+                let (angle_min, angle_step) = lidar_angles(*rays, *fov, heading);
+                (0..(*rays).max(1)).map(|i| (angle_min + angle_step * i as f32, *range)).collect()
+            }
+            SensorConfig::Proximity { angle, range, .. } => vec![(heading + angle, *range)],
+            SensorConfig::Bump { angle, .. } => vec![(heading + angle, CHARACTER_OCCUPY_WIDTH / 2.0 + BUMP_DISTANCE)],
+        }
+    }
+
+    // 从 (x, z) 朝 heading 方向读取, 噪声从 rng 取, 由调用方控制随机种子
+    pub fn read(&self, grid: &Grid, x: f32, z: f32, heading: f32, rng: &mut impl Rng) -> SensorReading {
+        let origin = ThreeGrid { x, z };
+        match self {
+            SensorConfig::Lidar { name, rays, range, fov, noise } => {
+                let (angle_min, angle_step) = lidar_angles(*rays, *fov, heading);
+
+                let ranges = (0..(*rays).max(1))
+                    .map(|i| {
+                        let distance = cast(grid, origin, angle_min + angle_step * i as f32, *range).map(|(distance, _)| distance).unwrap_or(*range);
+                        if *noise > 0.0 {
+                            (distance + rng.random_range(-*noise..=*noise)).clamp(0.0, *range)
+                        } else {
+                            distance
+                        }
+                    })
+                    .collect();
+
+                SensorReading::Lidar {
+                    name: name.clone(),
+                    angle_min,
+                    angle_step,
+                    ranges,
+                }
+            }
+            SensorConfig::Proximity { name, angle, range } => {
+                let hit = cast(grid, origin, heading + angle, *range);
+                SensorReading::Proximity {
+                    name: name.clone(),
+                    distance: hit.map(|(distance, _)| distance),
+                    obstacle_id: hit.and_then(|(_, id)| id),
+                }
+            }
+            SensorConfig::Bump { name, angle } => {
+                let reach = CHARACTER_OCCUPY_WIDTH / 2.0 + BUMP_DISTANCE;
+                SensorReading::Bump {
+                    name: name.clone(),
+                    pressed: cast(grid, origin, heading + angle, reach).is_some(),
+                }
+            }
+        }
+    }
+}
+
+// 激光雷达第一条射线的角度和射线间隔, 360° 时首尾射线重合, 按 rays 等分, 否则包含两端
+fn lidar_angles(rays: u32, fov: f32, heading: f32) -> (f32, f32) {
+    let rays = rays.max(1);
+    let angle_step = if fov >= TAU || rays == 1 { fov / rays as f32 } else { fov / (rays - 1) as f32 };
+    let angle_min = if fov >= TAU { heading } else { heading - fov / 2.0 };
+    (angle_min, angle_step)
+}
+
+// 沿 angle 方向发射射线, 返回 (距离, 障碍物 id)
+fn cast(grid: &Grid, origin: ThreeGrid, angle: f32, range: f32) -> Option<(f32, Option<u32>)> {
+    let direction = ThreeGrid { x: angle.sin(), z: angle.cos() };
+    raycast(grid, origin, direction, range, RayMask::Solid).map(|hit| (hit.distance, hit.obstacle.map(|obstacle| obstacle.id)))
+}
@@ -0,0 +1,519 @@
+/*!
+  录制与回放
+
+  录制时记录开始时的世界和机器人, 之后按顺序记录每个输入命令和每帧之后所有机器人的状态:
+  ```
+   - setup: 格子尺寸、模拟时间、障碍物类型、世界存档、机器人(生成参数和配置)
+   - inputs: 机器人命令(spawn | despawn | target | plan | clear | action | emote | update | tick)
+             世界编辑(障碍物、动态障碍物、区域、标记点, generate 带随机种子)
+             机器人配置(运动学、运动模型、路径跟踪、传感器、迷雾、探索、避障、行为树)
+   - frames: 每个 update | tick 之后所有机器人的状态, frames[i] 为第 i + 1 帧
+  ```
+  开始录制时当前世界用 setup 重建一次, 保证与回放的初始状态完全一致:
+  机器人停在当前位置, 保留朝向、动作和配置, 正在进行的移动、表情不保留, 行为树从头执行, 迷雾模式的认知地图重新开始
+  随机数都来自记录的种子(障碍物生成、传感器噪声), 相同的输入在同一版本中得到完全相同的结果
+  回放在独立的世界中进行, 不影响当前模拟:
+  ```
+   - seek: 跳到某一帧, 往回跳时从头重新执行
+   - verify: 从头执行所有帧并与记录的状态比较, 返回第一处不一致, 用于比较两个版本的输出
+  ```
+  机器人脚本(`script`)产生的目标、动作、表情作为输入记录, 回放时不运行脚本
+*/
+
+use crate::error::Error;
+use crate::module::animation::RobotAction;
+use crate::module::behaviour::{Behaviour, BehaviourStatus};
+use crate::module::dynamic::DynamicSchedule;
+use crate::module::grid::Grid;
+use crate::module::kinematics::{Kinematics, MotionModel};
+use crate::module::mapf::MapfSolver;
+use crate::module::marker::MarkerKind;
+use crate::module::obstacle::{ObstacleDef, ObstacleRegistry};
+use crate::module::robot::{Robot, RobotState};
+use crate::module::robots::{RobotGoal, RobotRegistry};
+use crate::module::sensor::SensorConfig;
+use crate::module::shape::ObstacleShape;
+use crate::module::tracking::Tracking;
+use crate::module::world::WorldSnapshot;
+use crate::module::zone::{ZoneEffect, ZoneShape};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const SESSION_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SessionInput {
+    Spawn { x: f32, z: f32, speed: f32 },
+    Despawn { id: u32 },
+    Target { id: u32, x: f32, z: f32 },
+    Plan { goals: Vec<RobotGoal>, solver: MapfSolver },
+    Clear { id: u32 },
+    Action { id: u32, action: String },
+    Emote { id: u32, emote: String },
+    Generate { kind: String, nums: usize, seed: u64 },
+    Update { id: u32, delta: f32 }, // 只更新一个机器人, 一帧
+    Tick { delta: f32 },            // 更新所有机器人, 一帧
+    // 世界编辑
+    Flag { x: f32, z: f32 },
+    AddMarker { name: String, kind: MarkerKind, x: f32, z: f32, metadata: serde_json::Value },
+    MoveMarker { id: u32, x: f32, z: f32 },
+    RemoveMarker { id: u32 },
+    LoadTypes { types: Vec<ObstacleDef> },
+    ClearObstacles,
+    AddObstacle { kind: String, gx: i32, gz: i32, width: Option<usize>, depth: Option<usize> },
+    AddShaped { kind: String, shape: ObstacleShape },
+    RemoveObstacle { id: u32 },
+    MoveObstacle { id: u32, gx: i32, gz: i32 },
+    ResizeObstacle { id: u32, width: usize, depth: usize },
+    Dynamic { id: u32, schedule: Option<DynamicSchedule> },
+    Open { id: u32, open: bool },
+    AddZone { name: String, shape: ZoneShape, effect: ZoneEffect },
+    RemoveZone { id: u32 },
+    LoadWorld { world: WorldSnapshot },
+    // 机器人配置
+    Kinematics { id: u32, kinematics: Kinematics },
+    Motion { id: u32, motion: MotionModel },
+    Tracking { id: u32, tracking: Tracking },
+    Sensors { id: u32, sensors: Vec<SensorConfig> },
+    Fog { id: u32, enabled: bool },
+    Exploring { id: u32, enabled: bool },
+    Avoidance { enabled: bool },
+    Behaviour { id: u32, behaviour: Option<Behaviour> }, // 设置|加载|清除行为树
+    Blackboard { id: u32, key: String, value: serde_json::Value },
+}
+
+// 开始录制时机器人的生成参数和配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RobotSetup {
+    pub id: u32,
+    pub x: f32,
+    pub z: f32,
+    #[serde(rename = "rotationY")]
+    pub rotation_y: f32,
+    pub speed: f32, // 生成时的速度
+    pub action: RobotAction,
+    pub kinematics: Kinematics,
+    pub motion: MotionModel,
+    pub tracking: Tracking,
+    pub sensors: Vec<SensorConfig>,
+    pub behaviour: Option<Behaviour>, // 保留黑板, 从头执行
+    pub fog: bool,                    // 认知地图不保存, 重建时重新开始
+    pub exploring: bool,
+}
+
+// 开始录制时的世界
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionSetup {
+    pub width: usize,
+    pub height: usize,
+    pub time: f32,
+    pub types: Vec<ObstacleDef>,
+    pub world: WorldSnapshot,
+    pub robots: Vec<RobotSetup>,
+    #[serde(rename = "nextRobotId")]
+    pub next_robot_id: u32,
+    pub avoidance: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionLog {
+    pub version: u32,
+    pub setup: SessionSetup,
+    pub inputs: Vec<SessionInput>,
+    pub frames: Vec<Vec<RobotState>>,
+}
+
+// 回放与记录不一致的地方
+#[derive(Serialize, Debug, Clone)]
+pub struct Divergence {
+    pub frame: usize, // 从 1 开始, 与 seek 一致
+    #[serde(rename = "robotId")]
+    pub robot_id: Option<u32>, // 为空时机器人数量不一致
+    pub expected: Option<RobotState>,
+    pub actual: Option<RobotState>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct ReplayStatus {
+    pub frame: usize,
+    pub frames: usize,
+    pub inputs: usize,
+}
+
+impl SessionInput {
+    // 是否推进一帧
+    pub fn is_frame(&self) -> bool {
+        matches!(self, SessionInput::Update { .. } | SessionInput::Tick { .. })
+    }
+
+    pub fn apply(&self, grid: &mut Grid, robots: &mut RobotRegistry) -> Result<(), String> {
+        match self {
+            SessionInput::Spawn { x, z, speed } => robots.spawn(grid, *x, *z, *speed).map(|_| ()),
+            SessionInput::Despawn { id } => robots.despawn(grid, *id).map(|_| ()),
+            SessionInput::Target { id, x, z } => robots.set_target(grid, *id, *x, *z).map(|_| ()),
+            SessionInput::Plan { goals, solver } => robots.plan_group(grid, goals, *solver).map(|_| ()),
+            SessionInput::Clear { id } => robots.get_mut(*id).map(|robot| robot.clear_path()),
+            SessionInput::Action { id, action } => robots.get_mut(*id)?.set_action(action),
+            SessionInput::Emote { id, emote } => robots.get_mut(*id)?.set_emote(emote),
+            SessionInput::Generate { kind, nums, seed } => grid.generate_obstacle(*nums, kind, *seed).map(|_| ()),
+            SessionInput::Update { id, delta } => robots.update(grid, *id, *delta).map(|_| ()),
+            SessionInput::Tick { delta } => {
+                robots.tick(grid, *delta);
+                Ok(())
+            }
+            SessionInput::Flag { x, z } => {
+                grid.place_flag(*x, *z);
+                Ok(())
+            }
+            SessionInput::AddMarker { name, kind, x, z, metadata } => grid.add_marker(name, *kind, *x, *z, metadata.clone()).map(|_| ()),
+            SessionInput::MoveMarker { id, x, z } => grid.move_marker(*id, *x, *z).map(|_| ()),
+            SessionInput::RemoveMarker { id } => grid.remove_marker(*id).map(|_| ()),
+            SessionInput::LoadTypes { types } => {
+                grid.set_registry(ObstacleRegistry::from_defs(types.clone())?);
+                Ok(())
+            }
+            SessionInput::ClearObstacles => {
+                grid.clear_obstacles();
+                Ok(())
+            }
+            SessionInput::AddObstacle { kind, gx, gz, width, depth } => grid.add_obstacle(kind, *gx, *gz, *width, *depth).map(|_| ()),
+            SessionInput::AddShaped { kind, shape } => grid.add_shaped_obstacle(kind, shape.clone()).map(|_| ()),
+            SessionInput::RemoveObstacle { id } => grid.remove_obstacle(*id).map(|_| ()),
+            SessionInput::MoveObstacle { id, gx, gz } => grid.move_obstacle(*id, *gx, *gz).map(|_| ()),
+            SessionInput::ResizeObstacle { id, width, depth } => grid.resize_obstacle(*id, *width, *depth).map(|_| ()),
+            SessionInput::Dynamic { id, schedule } => grid.make_dynamic(*id, *schedule).map(|_| ()),
+            SessionInput::Open { id, open } => grid.set_dynamic_open(*id, *open).map(|_| ()),
+            SessionInput::AddZone { name, shape, effect } => grid.add_zone(name, shape.clone(), *effect).map(|_| ()),
+            SessionInput::RemoveZone { id } => grid.remove_zone(*id).map(|_| ()),
+            SessionInput::LoadWorld { world } => grid.restore(world.clone()),
+            SessionInput::Kinematics { id, kinematics } => robots.get_mut(*id)?.set_kinematics(*kinematics),
+            SessionInput::Motion { id, motion } => robots.get_mut(*id)?.set_motion_model(grid, *motion),
+            SessionInput::Tracking { id, tracking } => robots.get_mut(*id)?.set_tracking(*tracking),
+            SessionInput::Sensors { id, sensors } => robots.get_mut(*id)?.set_sensors(grid, sensors.clone()),
+            SessionInput::Fog { id, enabled } => {
+                robots.get_mut(*id)?.set_fog(grid, *enabled);
+                Ok(())
+            }
+            SessionInput::Exploring { id, enabled } => robots.get_mut(*id)?.set_exploring(*enabled),
+            SessionInput::Avoidance { enabled } => {
+                robots.set_avoidance(*enabled);
+                Ok(())
+            }
+            SessionInput::Behaviour { id, behaviour } => {
+                robots.get_mut(*id)?.set_behaviour(behaviour.clone());
+                Ok(())
+            }
+            SessionInput::Blackboard { id, key, value } => robots.get_mut(*id)?.set_blackboard(key.clone(), value.clone()),
+        }
+    }
+}
+
+impl RobotSetup {
+    pub fn capture(robot: &Robot) -> Self {
+        let current = robot.get_current();
+        let behaviour = robot.get_behaviour().map(|behaviour| {
+            let mut tree = behaviour.tree.clone();
+            tree.reset();
+            Behaviour {
+                tree,
+                blackboard: behaviour.blackboard.clone(),
+                status: BehaviourStatus::Running,
+            }
+        });
+
+        Self {
+            id: robot.get_id(),
+            x: current.x,
+            z: current.z,
+            rotation_y: robot.get_rotation_y(),
+            speed: robot.get_base_speed(),
+            action: robot.get_animation().action(),
+            kinematics: robot.get_kinematics(),
+            motion: robot.get_motion_model(),
+            tracking: robot.get_tracking(),
+            sensors: robot.get_sensors().to_vec(),
+            behaviour,
+            fog: robot.get_belief().is_some(),
+            exploring: robot.is_exploring(),
+        }
+    }
+
+    // 按生成参数重建机器人并应用配置, 迷雾和探索需要在加入 `RobotRegistry` 之后设置
+    fn build(&self, grid: &mut Grid) -> Result<Robot, String> {
+        let mut robot = Robot::new(self.id, self.x, self.z, self.speed);
+        robot.set_rotation_y(self.rotation_y);
+        robot.set_kinematics(self.kinematics)?;
+        robot.set_motion_model(grid, self.motion)?;
+        robot.set_tracking(self.tracking)?;
+        robot.set_sensors(grid, self.sensors.clone())?;
+        robot.apply_action(self.action)?;
+        robot.set_behaviour(self.behaviour.clone());
+        // 恢复动作不是新的切换, 不推送
+        robot.take_animation_transitions();
+        Ok(robot)
+    }
+}
+
+impl SessionSetup {
+    pub fn capture(grid: &Grid, robots: &RobotRegistry) -> Self {
+        Self {
+            width: grid.width(),
+            height: grid.height(),
+            time: grid.time(),
+            types: grid.registry().defs().to_vec(),
+            world: grid.snapshot(),
+            robots: robots.robots().map(RobotSetup::capture).collect(),
+            next_robot_id: robots.next_id(),
+            avoidance: robots.is_avoidance(),
+        }
+    }
+
+    // 重建世界和机器人
+    pub fn build(&self) -> Result<(Grid, RobotRegistry), String> {
+        let mut grid = Grid::new(self.width, self.height);
+        grid.set_registry(ObstacleRegistry::from_defs(self.types.clone())?);
+        grid.restore(self.world.clone())?;
+        grid.set_time(self.time);
+
+        let built = self.robots.iter().map(|setup| setup.build(&mut grid)).collect::<Result<Vec<_>, _>>()?;
+        let mut robots = RobotRegistry::from_robots(&mut grid, built, self.next_robot_id, self.avoidance);
+        for setup in self.robots.iter().filter(|setup| setup.fog) {
+            let robot = robots.get_mut(setup.id)?;
+            robot.set_fog(&grid, true);
+            robot.set_exploring(setup.exploring)?;
+        }
+
+        Ok((grid, robots))
+    }
+}
+
+impl SessionLog {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        std::fs::write(path, content).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        let log: Self = serde_json::from_str(&content).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        if log.version != SESSION_VERSION {
+            return Err(format!("unsupported session version {}", log.version));
+        }
+
+        Ok(log)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    session: Option<SessionLog>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.session.is_some()
+    }
+
+    // 开始录制, 当前世界用 setup 重建, 与回放的初始状态一致
+    pub fn start(&mut self, grid: &mut Grid, robots: &mut RobotRegistry) -> Result<(), String> {
+        let setup = SessionSetup::capture(grid, robots);
+        (*grid, *robots) = setup.build()?;
+        self.session = Some(SessionLog {
+            version: SESSION_VERSION,
+            setup,
+            inputs: Vec::new(),
+            frames: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    // 记录输入, 推进一帧的输入请使用 `record_frame`
+    pub fn record(&mut self, input: SessionInput) {
+        if let Some(session) = &mut self.session {
+            session.inputs.push(input);
+        }
+    }
+
+    // 记录推进一帧的输入和该帧之后所有机器人的状态
+    pub fn record_frame(&mut self, input: SessionInput, states: Vec<RobotState>) {
+        if let Some(session) = &mut self.session {
+            session.inputs.push(input);
+            session.frames.push(states);
+        }
+    }
+
+    pub fn stop(&mut self) -> Result<SessionLog, String> {
+        self.session.take().ok_or_else(|| "not recording".to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    log: SessionLog,
+    grid: Grid,
+    robots: RobotRegistry,
+    cursor: usize, // 下一个执行的输入
+    frame: usize,  // 已执行的帧数
+}
+
+impl Replay {
+    pub fn new(log: SessionLog) -> Result<Self, String> {
+        let (grid, robots) = log.setup.build()?;
+        Ok(Self { log, grid, robots, cursor: 0, frame: 0 })
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            frame: self.frame,
+            frames: self.log.frames.len(),
+            inputs: self.log.inputs.len(),
+        }
+    }
+
+    pub fn states(&self) -> Vec<RobotState> {
+        self.robots.states()
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    // 回到第 0 帧
+    fn rewind(&mut self) -> Result<(), String> {
+        (self.grid, self.robots) = self.log.setup.build()?;
+        self.cursor = 0;
+        self.frame = 0;
+        Ok(())
+    }
+
+    // 执行输入直到推进一帧
+    fn step(&mut self) -> Result<(), String> {
+        while let Some(input) = self.log.inputs.get(self.cursor) {
+            self.cursor += 1;
+            input.apply(&mut self.grid, &mut self.robots).map_err(|err| format!("input {} failed: {}", self.cursor - 1, err))?;
+            if input.is_frame() {
+                self.frame += 1;
+                return Ok(());
+            }
+        }
+
+        Err(format!("session has only {} frames", self.frame))
+    }
+
+    // 跳到第 frame 帧(执行完 frame 帧之后), 返回所有机器人的状态
+    pub fn seek(&mut self, frame: usize) -> Result<Vec<RobotState>, String> {
+        if frame > self.log.frames.len() {
+            return Err(format!("frame {} out of range, session has {} frames", frame, self.log.frames.len()));
+        }
+
+        if frame < self.frame {
+            self.rewind()?;
+        }
+
+        while self.frame < frame {
+            self.step()?;
+        }
+
+        Ok(self.states())
+    }
+
+    // 从头执行所有帧并与记录比较, 返回第一处不一致
+    pub fn verify(&mut self) -> Result<Option<Divergence>, String> {
+        self.rewind()?;
+        while self.frame < self.log.frames.len() {
+            self.step()?;
+
+            if let Some(divergence) = compare(self.frame, &self.log.frames[self.frame - 1], &self.robots.states()) {
+                return Ok(Some(divergence));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// 比较同一帧的状态, 机器人按 id 排序
+fn compare(frame: usize, expected: &[RobotState], actual: &[RobotState]) -> Option<Divergence> {
+    for (e, a) in expected.iter().zip(actual) {
+        if e != a {
+            return Some(Divergence {
+                frame,
+                robot_id: Some(e.id),
+                expected: Some(*e),
+                actual: Some(*a),
+            });
+        }
+    }
+
+    if expected.len() != actual.len() {
+        return Some(Divergence {
+            frame,
+            robot_id: None,
+            expected: expected.get(actual.len()).copied(),
+            actual: actual.get(expected.len()).copied(),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按命令的方式执行输入并录制
+    fn run(recorder: &mut Recorder, grid: &mut Grid, robots: &mut RobotRegistry, input: SessionInput) {
+        input.apply(grid, robots).unwrap();
+        if input.is_frame() {
+            recorder.record_frame(input, robots.states());
+        } else {
+            recorder.record(input);
+        }
+    }
+
+    // 录制 → 保存 → 回放, verify 没有不一致
+    #[test]
+    fn record_replay_round_trip() {
+        let mut grid = Grid::new(100, 100);
+        let mut robots = RobotRegistry::new();
+        let a = robots.spawn(&mut grid, -20.0, -20.0, 2.0).unwrap().get_id();
+        robots.set_target(&mut grid, a, 0.0, 0.0).unwrap();
+        robots.tick(&mut grid, 0.05);
+
+        let mut recorder = Recorder::new();
+        recorder.start(&mut grid, &mut robots).unwrap();
+        let (grid, robots) = (&mut grid, &mut robots);
+        run(&mut recorder, grid, robots, SessionInput::Generate { kind: "rock".to_string(), nums: 30, seed: 47 });
+        run(&mut recorder, grid, robots, SessionInput::Spawn { x: 20.0, z: 20.0, speed: 3.0 });
+        run(&mut recorder, grid, robots, SessionInput::Avoidance { enabled: true });
+        run(&mut recorder, grid, robots, SessionInput::Target { id: a, x: 25.0, z: 15.0 });
+        run(&mut recorder, grid, robots, SessionInput::Target { id: a + 1, x: -25.0, z: -15.0 });
+        for i in 0..300 {
+            if i == 100 {
+                run(&mut recorder, grid, robots, SessionInput::Emote { id: a, emote: "wave".to_string() });
+            }
+
+            let input = match i % 10 {
+                0 => SessionInput::Update { id: a + 1, delta: 0.03 },
+                _ => SessionInput::Tick { delta: 0.05 },
+            };
+            run(&mut recorder, grid, robots, input);
+        }
+
+        let log = recorder.stop().unwrap();
+        assert_eq!(log.frames.len(), 300);
+        assert_ne!(log.frames.first(), log.frames.last());
+
+        let json = serde_json::to_string(&log).unwrap();
+        let mut replay = Replay::new(serde_json::from_str(&json).unwrap()).unwrap();
+        assert!(replay.verify().unwrap().is_none());
+        assert_eq!(replay.seek(150).unwrap(), log.frames[149]);
+        assert_eq!(replay.seek(300).unwrap(), robots.states());
+    }
+}
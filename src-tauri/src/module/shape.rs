@@ -0,0 +1,267 @@
+/*!
+  障碍物形状
+
+  形状使用世界坐标描述, 按保守策略光栅化到格子上: 只要格子和形状有重叠, 该格子就算被占用
+  ```
+   - rotated: 旋转矩形, 中心 + 宽高 + 绕 Y 轴旋转角度(弧度)
+   - circle: 圆形柱子
+   - polygon: 任意简单多边形(L 形石头、斜墙等)
+  ```
+*/
+
+use crate::error::Error;
+use crate::module::coord::CoordinateSystem;
+use crate::module::grid::{GridPoint, ThreeGrid, MAX_OBSTACLE_AREA, MAX_OBSTACLE_SIZE};
+use serde::{Deserialize, Serialize};
+
+// 格子收缩容差, 避免形状边界刚好压在格子边线上时多占一圈
+const EPSILON: f32 = 1e-4;
+
+// 世界坐标绝对值上限, 超出后 f32 精度不足以区分格子
+const MAX_COORD: f32 = 1e6;
+
+// 多边形顶点数上限, 光栅化时每个格子都要和所有边求交
+const MAX_POLYGON_POINTS: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ObstacleShape {
+    Rotated { x: f32, z: f32, width: f32, depth: f32, angle: f32 },
+    Circle { x: f32, z: f32, radius: f32 },
+    Polygon { points: Vec<ThreeGrid> },
+}
+
+// 世界坐标下的轴对齐包围盒
+struct Bounds {
+    min_x: f32,
+    min_z: f32,
+    max_x: f32,
+    max_z: f32,
+}
+
+impl ObstacleShape {
+    // 形状中心(多边形取顶点平均)
+    pub fn center(&self) -> ThreeGrid {
+        match self {
+            ObstacleShape::Rotated { x, z, .. } | ObstacleShape::Circle { x, z, .. } => ThreeGrid { x: *x, z: *z },
+            ObstacleShape::Polygon { points } => {
+                let n = points.len().max(1) as f32;
+                let x = points.iter().map(|p| p.x).sum::<f32>() / n;
+                let z = points.iter().map(|p| p.z).sum::<f32>() / n;
+                ThreeGrid { x, z }
+            }
+        }
+    }
+
+    // 平移
+    pub fn translate(&self, dx: f32, dz: f32) -> Self {
+        match self {
+            ObstacleShape::Rotated { x, z, width, depth, angle } => ObstacleShape::Rotated {
+                x: x + dx,
+                z: z + dz,
+                width: *width,
+                depth: *depth,
+                angle: *angle,
+            },
+            ObstacleShape::Circle { x, z, radius } => ObstacleShape::Circle { x: x + dx, z: z + dz, radius: *radius },
+            ObstacleShape::Polygon { points } => ObstacleShape::Polygon {
+                points: points.iter().map(|p| ThreeGrid { x: p.x + dx, z: p.z + dz }).collect(),
+            },
+        }
+    }
+
+    /**
+      校验形状, 光栅化前调用
+      ```
+       - 所有数值有限, 坐标不超过 ±1e6, 尺寸、半径大于 0
+       - 多边形 3 ~ 256 个顶点
+       - 包围盒不超过障碍物的边长和面积上限
+      ```
+    */
+    pub fn validate(&self, coords: &CoordinateSystem) -> Result<(), String> {
+        let coord = |v: &f32| v.abs() <= MAX_COORD;
+        let valid = match self {
+            ObstacleShape::Rotated { x, z, width, depth, angle } => coord(x) && coord(z) && angle.is_finite() && width.is_finite() && depth.is_finite() && *width > 0.0 && *depth > 0.0,
+            ObstacleShape::Circle { x, z, radius } => coord(x) && coord(z) && radius.is_finite() && *radius > 0.0,
+            ObstacleShape::Polygon { points } => (3..=MAX_POLYGON_POINTS).contains(&points.len()) && points.iter().all(|p| coord(&p.x) && coord(&p.z)),
+        };
+        if !valid {
+            return Err(Error::convert_string("obstacle shape has invalid values"));
+        }
+
+        let bounds = self.bounds(&self.to_polygon());
+        let a = coords.world_to_cell(bounds.min_x, bounds.min_z);
+        let b = coords.world_to_cell(bounds.max_x, bounds.max_z);
+        let width = (a.gx as i64 - b.gx as i64).unsigned_abs() + 1;
+        let depth = (a.gz as i64 - b.gz as i64).unsigned_abs() + 1;
+        if width > MAX_OBSTACLE_SIZE as u64 || depth > MAX_OBSTACLE_SIZE as u64 || width * depth > MAX_OBSTACLE_AREA as u64 {
+            return Err(Error::convert_string(&format!(
+                "obstacle shape covers {}x{} cells, exceeds the limit ({} per side, {} cells)",
+                width, depth, MAX_OBSTACLE_SIZE, MAX_OBSTACLE_AREA
+            )));
+        }
+
+        Ok(())
+    }
+
+    // 光栅化到格子
+    pub fn rasterize(&self, coords: &CoordinateSystem) -> Vec<GridPoint> {
+        let polygon = match self {
+            ObstacleShape::Rotated { .. } | ObstacleShape::Polygon { .. } => self.to_polygon(),
+            ObstacleShape::Circle { .. } => Vec::new(),
+        };
+
+        if let ObstacleShape::Polygon { points } = self {
+            if points.len() < 3 {
+                return Vec::new();
+            }
+        }
+
+        let bounds = self.bounds(&polygon);
+        let a = coords.world_to_cell(bounds.min_x, bounds.min_z);
+        let b = coords.world_to_cell(bounds.max_x, bounds.max_z);
+
+        let mut cells = Vec::new();
+        for gx in a.gx.min(b.gx)..=a.gx.max(b.gx) {
+            for gz in a.gz.min(b.gz)..=a.gz.max(b.gz) {
+                let cell = Self::cell_bounds(coords, gx, gz);
+                let hit = match self {
+                    ObstacleShape::Circle { x, z, radius } => {
+                        // 圆心到格子的最近点
+                        let nx = x.clamp(cell.min_x, cell.max_x);
+                        let nz = z.clamp(cell.min_z, cell.max_z);
+                        (nx - x) * (nx - x) + (nz - z) * (nz - z) <= radius * radius
+                    }
+                    _ => Self::polygon_intersects(&polygon, &cell),
+                };
+
+                if hit {
+                    cells.push(GridPoint { gx, gz });
+                }
+            }
+        }
+
+        cells
+    }
+
+    // 旋转矩形、多边形统一转为顶点列表
+    fn to_polygon(&self) -> Vec<ThreeGrid> {
+        match self {
+            ObstacleShape::Rotated { x, z, width, depth, angle } => {
+                let (sin, cos) = angle.sin_cos();
+                let hw = width / 2.0;
+                let hd = depth / 2.0;
+                [(-hw, -hd), (hw, -hd), (hw, hd), (-hw, hd)]
+                    .iter()
+                    .map(|(lx, lz)| ThreeGrid {
+                        x: x + lx * cos - lz * sin,
+                        z: z + lx * sin + lz * cos,
+                    })
+                    .collect()
+            }
+            ObstacleShape::Polygon { points } => points.clone(),
+            ObstacleShape::Circle { .. } => Vec::new(),
+        }
+    }
+
+    fn bounds(&self, polygon: &[ThreeGrid]) -> Bounds {
+        if let ObstacleShape::Circle { x, z, radius } = self {
+            return Bounds {
+                min_x: x - radius,
+                min_z: z - radius,
+                max_x: x + radius,
+                max_z: z + radius,
+            };
+        }
+
+        let mut bounds = Bounds {
+            min_x: f32::MAX,
+            min_z: f32::MAX,
+            max_x: f32::MIN,
+            max_z: f32::MIN,
+        };
+
+        for p in polygon {
+            bounds.min_x = bounds.min_x.min(p.x);
+            bounds.min_z = bounds.min_z.min(p.z);
+            bounds.max_x = bounds.max_x.max(p.x);
+            bounds.max_z = bounds.max_z.max(p.z);
+        }
+
+        bounds
+    }
+
+    // 格子在世界坐标中的范围(向内收缩一点)
+    fn cell_bounds(coords: &CoordinateSystem, gx: i32, gz: i32) -> Bounds {
+        let a = coords.cell_f_to_world(gx as f32, gz as f32);
+        let b = coords.cell_f_to_world((gx + 1) as f32, (gz + 1) as f32);
+        Bounds {
+            min_x: a.x.min(b.x) + EPSILON,
+            min_z: a.z.min(b.z) + EPSILON,
+            max_x: a.x.max(b.x) - EPSILON,
+            max_z: a.z.max(b.z) - EPSILON,
+        }
+    }
+
+    /*
+      多边形与格子是否相交:
+      - 多边形顶点在格子内
+      - 格子角点在多边形内
+      - 多边形的边与格子的边相交
+    */
+    fn polygon_intersects(polygon: &[ThreeGrid], cell: &Bounds) -> bool {
+        let corners = [
+            ThreeGrid { x: cell.min_x, z: cell.min_z },
+            ThreeGrid { x: cell.max_x, z: cell.min_z },
+            ThreeGrid { x: cell.max_x, z: cell.max_z },
+            ThreeGrid { x: cell.min_x, z: cell.max_z },
+        ];
+
+        if polygon.iter().any(|p| p.x >= cell.min_x && p.x <= cell.max_x && p.z >= cell.min_z && p.z <= cell.max_z) {
+            return true;
+        }
+
+        if corners.iter().any(|c| Self::contains_point(polygon, c)) {
+            return true;
+        }
+
+        for i in 0..polygon.len() {
+            let a = &polygon[i];
+            let b = &polygon[(i + 1) % polygon.len()];
+            for j in 0..corners.len() {
+                if Self::segments_intersect(a, b, &corners[j], &corners[(j + 1) % corners.len()]) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    // 射线法判断点是否在多边形内
+    fn contains_point(polygon: &[ThreeGrid], p: &ThreeGrid) -> bool {
+        let mut inside = false;
+        let mut j = polygon.len() - 1;
+        for i in 0..polygon.len() {
+            let a = &polygon[i];
+            let b = &polygon[j];
+            if (a.z > p.z) != (b.z > p.z) && p.x < (b.x - a.x) * (p.z - a.z) / (b.z - a.z) + a.x {
+                inside = !inside;
+            }
+            j = i;
+        }
+
+        inside
+    }
+
+    fn segments_intersect(p1: &ThreeGrid, p2: &ThreeGrid, q1: &ThreeGrid, q2: &ThreeGrid) -> bool {
+        let cross = |a: &ThreeGrid, b: &ThreeGrid, c: &ThreeGrid| (b.x - a.x) * (c.z - a.z) - (b.z - a.z) * (c.x - a.x);
+
+        let d1 = cross(q1, q2, p1);
+        let d2 = cross(q1, q2, p2);
+        let d3 = cross(p1, p2, q1);
+        let d4 = cross(p1, p2, q2);
+
+        ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    }
+}
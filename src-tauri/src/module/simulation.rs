@@ -0,0 +1,150 @@
+/*!
+  固定步长的模拟调度
+
+  后台线程每隔 1 / TICK_RATE 秒检查一次, 按经过的真实时间推进模拟:
+  ```
+   - 经过的时间 * time_scale 累加到 accumulator, 每满一个 TICK_DELTA 执行一次 tick
+   - 每次最多执行 MAX_TICKS_PER_FRAME 次 tick, 追不上时丢弃多余的时间, 防止越积越多
+   - 暂停时不累加时间, 只执行 step 请求的 tick
+   - 每次执行 tick 后推送 `simulation-snapshot` 事件
+  ```
+  模拟运行时由后台线程推进, `on_update_robot_position`、`tick_robots` 只返回当前状态, 暂停后才按传入的 delta 推进
+*/
+
+use crate::module::robot::RobotState;
+use serde::{Deserialize, Serialize};
+
+pub const TICK_RATE: f32 = 60.0;
+
+// 每次 tick 推进的模拟时间(秒)
+pub const TICK_DELTA: f32 = 1.0 / TICK_RATE;
+
+// 每次检查最多执行的 tick 数, 需要大于 MAX_TIME_SCALE
+const MAX_TICKS_PER_FRAME: u32 = 32;
+
+const MAX_TIME_SCALE: f32 = 16.0;
+
+// 单次 step 最多的 tick 数
+const MAX_STEPS: u32 = 600;
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    paused: bool,
+    time_scale: f32,
+    accumulator: f32,
+    pending_steps: u32, // 暂停时等待执行的 tick 数
+    tick: u64,
+    time: f64, // 模拟时间(秒)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SimulationStatus {
+    pub paused: bool,
+    #[serde(rename = "timeScale")]
+    pub time_scale: f32,
+    #[serde(rename = "tickRate")]
+    pub tick_rate: f32,
+    pub tick: u64,
+    pub time: f64,
+}
+
+// 推送给前端的快照
+#[derive(Serialize, Debug, Clone)]
+pub struct SimulationSnapshot {
+    pub tick: u64,
+    pub time: f64,
+    pub robots: Vec<RobotState>,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self {
+            paused: false,
+            time_scale: 1.0,
+            accumulator: 0.0,
+            pending_steps: 0,
+            tick: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.accumulator = 0.0;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    // 暂停时单步执行 count 个 tick
+    pub fn step(&mut self, count: u32) -> Result<(), String> {
+        if !self.paused {
+            return Err("simulation is running, pause it first".to_string());
+        }
+
+        if count == 0 || count > MAX_STEPS {
+            return Err(format!("step count must be between 1 and {}", MAX_STEPS));
+        }
+
+        self.pending_steps = (self.pending_steps + count).min(MAX_STEPS);
+        Ok(())
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) -> Result<(), String> {
+        if !(time_scale > 0.0 && time_scale <= MAX_TIME_SCALE) {
+            return Err(format!("time scale must be in (0, {}]", MAX_TIME_SCALE));
+        }
+
+        self.time_scale = time_scale;
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.paused
+    }
+
+    /**
+      经过 elapsed 秒真实时间, 返回本次需要执行的 tick 数
+      暂停时返回 step 请求的 tick 数
+    */
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        let ticks = if self.paused {
+            let ticks = self.pending_steps.min(MAX_TICKS_PER_FRAME);
+            self.pending_steps -= ticks;
+            ticks
+        } else {
+            self.accumulator += elapsed * self.time_scale;
+            let ticks = ((self.accumulator / TICK_DELTA) as u32).min(MAX_TICKS_PER_FRAME);
+            self.accumulator -= ticks as f32 * TICK_DELTA;
+
+            // 追不上, 丢弃多余的时间
+            if ticks == MAX_TICKS_PER_FRAME {
+                self.accumulator = self.accumulator.min(TICK_DELTA);
+            }
+
+            ticks
+        };
+
+        self.tick += ticks as u64;
+        self.time += ticks as f64 * TICK_DELTA as f64;
+        ticks
+    }
+
+    pub fn status(&self) -> SimulationStatus {
+        SimulationStatus {
+            paused: self.paused,
+            time_scale: self.time_scale,
+            tick_rate: TICK_RATE,
+            tick: self.tick,
+            time: self.time,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+/*!
+  格子存储
+
+  每个格子不再是一个带 `String` 的结构体, 而是拆成几层紧凑的数据:
+  ```
+   - blocked / occupied / flag: 位图, 每个格子 1 bit
+   - kinds: 障碍物类型编码, 每个格子 1 byte, 0 表示无
+   - obstacles: 障碍物 id, 每个格子 4 byte, 0 表示无
+  ```
+  2000 * 2000 的格子由原来的 ~128MB 降到 ~21MB
+  寻路、占用等热点路径只读写单独一层(`blocked`、`set_occupied`), 比原来的布局快几倍; `get` 需要解包所有层, 比原来慢约 3 倍
+  对比见 tests 中的 `memory_vs_legacy` 和 `speed_vs_legacy`
+*/
+
+use crate::module::grid::GridCell;
+#[cfg(test)]
+use std::mem::size_of;
+
+// 位图
+#[derive(Debug, Clone)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)] }
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn set(&mut self, i: usize, value: bool) {
+        let mask = 1 << (i % 64);
+        if value {
+            self.words[i / 64] |= mask;
+        } else {
+            self.words[i / 64] &= !mask;
+        }
+    }
+
+    // 全部置 0
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+
+    // 遍历所有为 1 的位
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(wi, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(wi * 64 + bit)
+            })
+        })
+    }
+
+    #[cfg(test)]
+    pub fn memory_bytes(&self) -> usize {
+        self.words.len() * size_of::<u64>()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CellStorage {
+    blocked: BitSet,
+    occupied: BitSet,
+    flags: BitSet,
+    kinds: Vec<u8>,
+    obstacles: Vec<u32>,
+}
+
+impl CellStorage {
+    pub fn new(len: usize) -> Self {
+        Self {
+            blocked: BitSet::new(len),
+            occupied: BitSet::new(len),
+            flags: BitSet::new(len),
+            kinds: vec![0; len],
+            obstacles: vec![0; len],
+        }
+    }
+
+    pub fn get(&self, i: usize) -> GridCell {
+        let obstacle = self.obstacles[i];
+        GridCell {
+            occupied: self.occupied.get(i),
+            has_flag: self.flags.get(i),
+            blocked: self.blocked.get(i),
+            blocked_type: if self.kinds[i] == 0 { None } else { Some(self.kinds[i]) },
+            obstacle_id: if obstacle == 0 { None } else { Some(obstacle) },
+        }
+    }
+
+    pub fn set(&mut self, i: usize, cell: &GridCell) {
+        self.occupied.set(i, cell.occupied);
+        self.flags.set(i, cell.has_flag);
+        self.blocked.set(i, cell.blocked);
+        self.kinds[i] = cell.blocked_type.unwrap_or(0);
+        self.obstacles[i] = cell.obstacle_id.unwrap_or(0);
+    }
+
+    // 只读写单独一层, 热点路径(寻路、占用)不需要解包整个格子
+    pub fn blocked(&self, i: usize) -> bool {
+        self.blocked.get(i)
+    }
+
+    pub fn set_occupied(&mut self, i: usize, occupied: bool) {
+        self.occupied.set(i, occupied);
+    }
+
+    pub fn occupied(&self) -> &BitSet {
+        &self.occupied
+    }
+
+    // 清除所有红旗
+    pub fn clear_flags(&mut self) {
+        self.flags.clear();
+    }
+
+    // 占用内存(字节)
+    #[cfg(test)]
+    pub fn memory_bytes(&self) -> usize {
+        self.blocked.memory_bytes() + self.occupied.memory_bytes() + self.flags.memory_bytes() + self.kinds.len() * size_of::<u8>() + self.obstacles.len() * size_of::<u32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    // 2000 * 2000 的格子
+    const LEN: usize = 2000 * 2000;
+
+    // 原来的格子布局, 只用于对比
+    #[allow(dead_code)]
+    #[derive(Debug, Default, Clone)]
+    struct LegacyCell {
+        occupied: bool,
+        has_flag: bool,
+        blocked: bool,
+        blocked_type: String,
+    }
+
+    // 10% 的格子是障碍物, 两种布局写入相同的数据
+    fn build() -> (CellStorage, Vec<LegacyCell>) {
+        let mut storage = CellStorage::new(LEN);
+        let mut legacy = vec![LegacyCell::default(); LEN];
+        let mut rng = StdRng::seed_from_u64(27);
+        for _ in 0..LEN / 10 {
+            let i = rng.random_range(0..LEN);
+            let cell = GridCell {
+                blocked: true,
+                blocked_type: Some(1),
+                obstacle_id: Some(1),
+                ..GridCell::default()
+            };
+            storage.set(i, &cell);
+            legacy[i].blocked = true;
+            legacy[i].blocked_type = "pillar".to_string();
+        }
+
+        (storage, legacy)
+    }
+
+    fn legacy_bytes(cells: &[LegacyCell]) -> usize {
+        cells.len() * size_of::<LegacyCell>() + cells.iter().map(|cell| cell.blocked_type.capacity()).sum::<usize>()
+    }
+
+    #[test]
+    fn memory_vs_legacy() {
+        let (storage, legacy) = build();
+        let packed = storage.memory_bytes();
+        let old = legacy_bytes(&legacy);
+        assert!(packed * 5 < old, "packed {} legacy {}", packed, old);
+    }
+
+    /**
+      读写速度对比, 耗时较长, 手动运行:
+      `cargo test --release -- --ignored speed_vs_legacy`
+    */
+    #[test]
+    #[ignore]
+    fn speed_vs_legacy() {
+        let (mut storage, mut legacy) = build();
+        let mut rng = StdRng::seed_from_u64(28);
+        let reads: Vec<usize> = (0..10_000_000).map(|_| rng.random_range(0..LEN)).collect();
+
+        // 预热, 避免第一项计时包含缺页和 CPU 升频
+        black_box(reads.iter().filter(|&&i| storage.get(i).blocked || legacy[i].blocked).count());
+
+        let time = |f: &mut dyn FnMut() -> usize| {
+            let start = Instant::now();
+            let result = black_box(f());
+            (start.elapsed(), result)
+        };
+
+        // 随机读 blocked(A* 扩展邻居)
+        let packed = time(&mut || reads.iter().filter(|&&i| storage.blocked(i)).count());
+        let old = time(&mut || reads.iter().filter(|&&i| legacy[i].blocked).count());
+        assert_eq!(packed.1, old.1);
+        assert!(packed.0 < old.0, "random read: packed {:?} legacy {:?}", packed.0, old.0);
+
+        // 读取整个格子需要解包所有层, 比原来慢, 只检查结果
+        let full = time(&mut || reads.iter().filter(|&&i| storage.get(i).blocked).count());
+        assert_eq!(full.1, old.1);
+
+        // 顺序扫描所有格子
+        let packed = time(&mut || (0..LEN).filter(|&i| storage.blocked(i)).count());
+        let old = time(&mut || legacy.iter().filter(|cell| cell.blocked).count());
+        assert_eq!(packed.1, old.1);
+        assert!(packed.0 < old.0, "scan: packed {:?} legacy {:?}", packed.0, old.0);
+
+        // 随机写 occupied(机器人移动)
+        let packed = time(&mut || {
+            for &i in &reads {
+                let occupied = storage.occupied().get(i);
+                storage.set_occupied(i, !occupied);
+            }
+            reads.len()
+        });
+        let old = time(&mut || {
+            for &i in &reads {
+                legacy[i].occupied = !legacy[i].occupied;
+            }
+            reads.len()
+        });
+        assert!(packed.0 < old.0, "random write: packed {:?} legacy {:?}", packed.0, old.0);
+        assert!((0..LEN).all(|i| storage.occupied().get(i) == legacy[i].occupied));
+    }
+}
@@ -0,0 +1,127 @@
+/*!
+  路径跟踪控制器
+
+  根据整条剩余路径计算机器人每帧的移动朝向:
+  ```
+   - direct: 直接朝当前路径点移动, 到达后切换下一个(默认, 带时间的路径始终使用)
+   - pursuit: 纯追踪, 沿路径从投影点前进 lookahead 得到预瞄点, 朝预瞄点移动, 拐角处提前转向
+   - stanley: 路径朝向 + atan(gain * 横向误差 / 速度), 偏离路径时转回路径
+  ```
+  路径为折线: 起点 + 路径点, 机器人投影到最近的线段上, 投影越过路径点即切换到下一个
+  横向误差(cross-track error)为机器人到投影点的距离, 正值表示在路径朝向 +90°(朝向增大方向)一侧
+*/
+
+use crate::module::kinematics::wrap_angle;
+use crate::module::orca::Vec2;
+use serde::{Deserialize, Serialize};
+
+// 投影时最多向前查找的路径长度, 防止路径自身交叉时跳到后面的线段
+const PROJECT_WINDOW: f32 = 5.0;
+
+// 投影距离相差小于该值时按相同处理
+const PROJECT_EPSILON: f32 = 1e-3;
+
+// Stanley 速度很小时的软化常数, 防止除零
+const STANLEY_SOFTENING: f32 = 0.1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Tracking {
+    #[default]
+    Direct,
+    Pursuit {
+        lookahead: f32,
+    },
+    Stanley {
+        gain: f32,
+    },
+}
+
+// 机器人在路径上的投影
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    pub segment: usize, // 所在线段, 从 points[segment] 到 points[segment + 1]
+    pub point: Vec2,
+    pub error: f32, // 横向误差
+}
+
+impl Tracking {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Tracking::Pursuit { lookahead } if !(lookahead.is_finite() && *lookahead > 0.0) => Err("lookahead must be finite and positive".to_string()),
+            Tracking::Stanley { gain } if !(gain.is_finite() && *gain > 0.0) => Err("gain must be finite and positive".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /**
+      期望的移动方向(单位向量), direct 时返回 None
+      - points: 路径折线
+      - projection: 机器人在路径上的投影
+      - speed: 当前速度(stanley)
+    */
+    pub fn direction(&self, points: &[Vec2], projection: &Projection, position: Vec2, speed: f32) -> Option<Vec2> {
+        match *self {
+            Tracking::Direct => None,
+            Tracking::Pursuit { lookahead } => Some((advance(points, projection, lookahead) - position).normalize()),
+            Tracking::Stanley { gain } => {
+                let direction = points[projection.segment + 1] - points[projection.segment];
+                let yaw = wrap_angle(direction.x.atan2(direction.z) - (gain * projection.error).atan2(speed + STANLEY_SOFTENING));
+                Some(Vec2::new(yaw.sin(), yaw.cos()))
+            }
+        }
+    }
+}
+
+// 从 from 开始向前找离 position 最近的线段
+pub fn project(points: &[Vec2], from: usize, position: Vec2) -> Projection {
+    let mut best = Projection {
+        segment: from,
+        point: points[from],
+        error: 0.0,
+    };
+    let mut best_distance = f32::INFINITY;
+    // 从第一段的投影点到当前线段起点的路径长度
+    let mut travelled = 0.0;
+
+    for segment in from..points.len().saturating_sub(1) {
+        if travelled > PROJECT_WINDOW {
+            break;
+        }
+
+        let (a, b) = (points[segment], points[segment + 1]);
+        let direction = b - a;
+        let length_sq = direction.length_sq();
+        let ratio = if length_sq > 0.0 { ((position - a).dot(direction) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+        let point = a + direction * ratio;
+        let distance = (position - point).length();
+
+        // 相同距离时取后面的线段, 已到达路径点时切换到下一段
+        if distance <= best_distance + PROJECT_EPSILON {
+            let error = if length_sq > 0.0 { (position - point).det(direction.normalize()) } else { 0.0 };
+            best = Projection { segment, point, error };
+            best_distance = distance;
+        }
+
+        travelled += if segment == from { (b - point).length() } else { length_sq.sqrt() };
+    }
+
+    best
+}
+
+// 从投影点沿路径前进 distance, 超出终点时返回终点
+fn advance(points: &[Vec2], projection: &Projection, distance: f32) -> Vec2 {
+    let mut from = projection.point;
+    let mut remaining = distance;
+    for p in &points[projection.segment + 1..] {
+        let length = (*p - from).length();
+        if length >= remaining {
+            return from + (*p - from) * (remaining / length);
+        }
+
+        remaining -= length;
+        from = *p;
+    }
+
+    from
+}
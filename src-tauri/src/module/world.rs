@@ -0,0 +1,37 @@
+/*!
+  世界存档
+
+  保存|加载障碍物、动态障碍物、标记点和区域, 格子状态在加载时根据这些数据重新生成
+*/
+
+use crate::error::Error;
+use crate::module::dynamic::DynamicObstacle;
+use crate::module::grid::Obstacle;
+use crate::module::marker::Marker;
+use crate::module::zone::Zone;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WorldSnapshot {
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub dynamics: Vec<DynamicObstacle>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+}
+
+impl WorldSnapshot {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        std::fs::write(path, content).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        serde_json::from_str(&content).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+}
@@ -0,0 +1,153 @@
+/*!
+  区域: 禁行区、减速区、高代价区
+
+  区域不是障碍物, 不会渲染成障碍物, 只影响规划和移动:
+  ```
+   - forbidden: 规划时视为阻挡
+   - slow: Robot::update 中速度乘以 factor, 规划时代价除以 factor
+   - cost: 规划时每经过一个格子额外增加 penalty
+  ```
+  格子是否在区域内按格子中心判断
+*/
+
+use crate::error::Error;
+use crate::module::grid::ThreeGrid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ZoneShape {
+    Rect {
+        #[serde(rename = "minX")]
+        min_x: f32,
+        #[serde(rename = "minZ")]
+        min_z: f32,
+        #[serde(rename = "maxX")]
+        max_x: f32,
+        #[serde(rename = "maxZ")]
+        max_z: f32,
+    },
+    Polygon {
+        points: Vec<ThreeGrid>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ZoneEffect {
+    Forbidden,
+    Slow { factor: f32 },
+    Cost { penalty: f64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Zone {
+    pub id: u32,
+    pub name: String,
+    pub shape: ZoneShape,
+    pub effect: ZoneEffect,
+}
+
+// 机器人进入|离开区域事件, 推送给前端
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ZoneEvent {
+    #[serde(rename = "robotId")]
+    pub robot_id: u32,
+    #[serde(rename = "zoneId")]
+    pub zone_id: u32,
+    pub name: String,
+    pub entered: bool,
+}
+
+// 区域在世界坐标下的包围盒, 查询某点所在区域时先按包围盒过滤
+#[derive(Debug, Clone, Copy)]
+pub struct ZoneBounds {
+    min_x: f32,
+    min_z: f32,
+    max_x: f32,
+    max_z: f32,
+}
+
+impl ZoneBounds {
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        x >= self.min_x && x <= self.max_x && z >= self.min_z && z <= self.max_z
+    }
+}
+
+impl ZoneEffect {
+    // 减速倍率在 (0, 1] 内, 额外代价不能为负
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ZoneEffect::Forbidden => Ok(()),
+            ZoneEffect::Slow { factor } if !(factor.is_finite() && factor > 0.0 && factor <= 1.0) => Err(Error::convert_string("slow zone factor must be in (0, 1]")),
+            ZoneEffect::Cost { penalty } if !(penalty.is_finite() && penalty >= 0.0) => Err(Error::convert_string("cost zone penalty must not be negative")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ZoneShape {
+    // 坐标有限, 矩形 min 不大于 max, 多边形至少 3 个顶点
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match self {
+            ZoneShape::Rect { min_x, min_z, max_x, max_z } => [min_x, min_z, max_x, max_z].iter().all(|v| v.is_finite()) && min_x <= max_x && min_z <= max_z,
+            ZoneShape::Polygon { points } => points.len() >= 3 && points.iter().all(|p| p.x.is_finite() && p.z.is_finite()),
+        };
+
+        if !valid {
+            return Err(Error::convert_string("zone shape has invalid values"));
+        }
+
+        Ok(())
+    }
+
+    pub fn bounds(&self) -> ZoneBounds {
+        match self {
+            ZoneShape::Rect { min_x, min_z, max_x, max_z } => ZoneBounds {
+                min_x: *min_x,
+                min_z: *min_z,
+                max_x: *max_x,
+                max_z: *max_z,
+            },
+            ZoneShape::Polygon { points } => points.iter().fold(
+                ZoneBounds {
+                    min_x: f32::MAX,
+                    min_z: f32::MAX,
+                    max_x: f32::MIN,
+                    max_z: f32::MIN,
+                },
+                |b, p| ZoneBounds {
+                    min_x: b.min_x.min(p.x),
+                    min_z: b.min_z.min(p.z),
+                    max_x: b.max_x.max(p.x),
+                    max_z: b.max_z.max(p.z),
+                },
+            ),
+        }
+    }
+
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        match self {
+            ZoneShape::Rect { min_x, min_z, max_x, max_z } => x >= *min_x && x <= *max_x && z >= *min_z && z <= *max_z,
+            ZoneShape::Polygon { points } => {
+                if points.len() < 3 {
+                    return false;
+                }
+
+                // 射线法
+                let mut inside = false;
+                let mut j = points.len() - 1;
+                for i in 0..points.len() {
+                    let a = &points[i];
+                    let b = &points[j];
+                    if (a.z > z) != (b.z > z) && x < (b.x - a.x) * (z - a.z) / (b.z - a.z) + a.x {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+
+                inside
+            }
+        }
+    }
+}